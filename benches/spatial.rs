@@ -0,0 +1,109 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use jiggleballs::config::JiggleBallsConfig;
+use jiggleballs::rng::Rng;
+use jiggleballs::simulation::{InputState, Simulation};
+use jiggleballs::spatial::{AnySpatialIndex, SpatialIndex, SpatialIndexKind};
+use jiggleballs::{bounding_box, gen_circle, Circle, CircleId};
+use macroquad::math::Rect;
+
+const WIDTH: f32 = 1920.0;
+const HEIGHT: f32 = 1080.0;
+// the gui's ball count slider goes up to 15000
+const BALL_COUNTS: [usize; 2] = [1_000, 15_000];
+const KINDS: [SpatialIndexKind; 2] = [SpatialIndexKind::Quadtree, SpatialIndexKind::Grid];
+const GRID_CELL_SIZE: f32 = 100.0;
+
+fn gen_circles(count: usize) -> Vec<Circle> {
+    let mut rng = Rng::new(1);
+    (0..count)
+        .map(|i| gen_circle(&mut rng, CircleId(i as u64), WIDTH, HEIGHT, 5.0, 50.0, 0.0))
+        .collect()
+}
+
+fn index_config(kind: SpatialIndexKind) -> JiggleBallsConfig {
+    JiggleBallsConfig {
+        spatial_index: kind,
+        grid_cell_size: GRID_CELL_SIZE,
+        ..Default::default()
+    }
+}
+
+fn rebuild(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rebuild");
+    for kind in KINDS {
+        for count in BALL_COUNTS {
+            let circles = gen_circles(count);
+            let mut index = AnySpatialIndex::new(bounding_box(WIDTH, HEIGHT), &index_config(kind));
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?}", kind), count),
+                &circles,
+                |b, circles| {
+                    b.iter(|| {
+                        index.rebuild(
+                            bounding_box(WIDTH, HEIGHT),
+                            circles.iter().map(|circ| circ.position).enumerate(),
+                        )
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn query_neighbours(c: &mut Criterion) {
+    let mut group = c.benchmark_group("query every ball's neighbours");
+    for kind in KINDS {
+        for count in BALL_COUNTS {
+            let circles = gen_circles(count);
+            let mut index = AnySpatialIndex::new(bounding_box(WIDTH, HEIGHT), &index_config(kind));
+            index.rebuild(
+                bounding_box(WIDTH, HEIGHT),
+                circles.iter().map(|circ| circ.position).enumerate(),
+            );
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?}", kind), count),
+                &circles,
+                |b, circles| {
+                    b.iter(|| {
+                        let mut found = 0;
+                        for circ in circles {
+                            let range = Rect::new(
+                                circ.position.x - 50.0,
+                                circ.position.y - 50.0,
+                                100.0,
+                                100.0,
+                            );
+                            found += index.query_rect(range).count();
+                        }
+                        black_box(found)
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn simulation_step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("simulation step");
+    group.sample_size(10);
+    for kind in KINDS {
+        for count in BALL_COUNTS {
+            let config = JiggleBallsConfig {
+                num_circles: count as u32,
+                seed: Some(1),
+                ..index_config(kind)
+            };
+            let mut simulation = Simulation::new(config, WIDTH, HEIGHT);
+            let input = InputState::default();
+            group.bench_function(BenchmarkId::new(format!("{:?}", kind), count), |b| {
+                b.iter(|| black_box(simulation.step(1.0 / 60.0, &input)))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, rebuild, query_neighbours, simulation_step);
+criterion_main!(benches);
@@ -0,0 +1,646 @@
+use crate::quadtree::OutOfBounds;
+use crate::rng::MAX_SEED;
+use crate::simulation::{BoundaryMode, CollisionModel, GravityTilt, Integrator, JiggleModel, Wall};
+use crate::spatial::SpatialIndexKind;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use toml::Value;
+
+/// All of the user-tweakable settings, as saved to and loaded from `config.toml`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct JiggleBallsConfig {
+    pub min_circle_size: f32,
+    pub max_circle_size: f32,
+    pub audio_enabled: bool,
+    pub is_fullscreen: bool,
+    pub jiggle: f32,
+    /// how the balls are jiggled: by a random kick of up to `jiggle` every step, or by
+    /// Brownian motion with the given `diffusion`
+    pub jiggle_model: JiggleModel,
+    /// the diffusion coefficient of Brownian balls, in square pixels per second; the mean square
+    /// of how far they've wandered grows by four times this every second
+    pub diffusion: f32,
+    /// how quickly the medium slows down Brownian balls, per second; it takes the place of
+    /// `medium_viscosity`
+    pub brownian_damping: f32,
+    /// whether each ball jiggles according to its own temperature (with `jiggle` scaled by the
+    /// square root of its temperature, or `diffusion` by the temperature itself) rather than
+    /// every ball jiggling the same
+    pub thermal: bool,
+    /// how quickly heat flows between touching balls
+    pub heat_conductivity: f32,
+    /// which wall heats up the balls near it, and which one cools them down
+    pub hot_wall: Wall,
+    pub cold_wall: Wall,
+    /// the temperatures balls near the hot and cold walls are brought towards
+    pub hot_temperature: f32,
+    pub cold_temperature: f32,
+    /// how far out from the hot and cold walls their heating and cooling reaches, in pixels
+    pub thermal_wall_depth: f32,
+    /// how quickly balls near the hot and cold walls take on their temperatures
+    pub wall_heat_rate: f32,
+    /// whether balls are coloured by how hot they are, from blue at `cold_temperature` to red
+    /// at `hot_temperature`, instead of by their own colour
+    pub color_by_temperature: bool,
+    pub mouse_repel_force: f32,
+    pub mouse_attract_force: f32,
+    pub mouse_attract_distance: f32,
+    pub medium_viscosity: f32,
+    /// whether the balls behave like a liquid (smoothed-particle hydrodynamics), with pressure
+    /// taking the place of ball collisions and the fluid's own viscosity replacing the
+    /// `medium_viscosity` drag
+    pub fluid: bool,
+    /// how far each ball's fluid is spread out; about three times the spacing between balls
+    /// works best
+    pub fluid_smoothing_radius: f32,
+    /// the density the fluid settles at; every ball carries the same amount of fluid whatever
+    /// its size, so this is roughly how many balls there are per square pixel
+    pub fluid_rest_density: f32,
+    /// how hard the fluid pushes back against being squashed above its rest density
+    pub fluid_stiffness: f32,
+    /// how thick the fluid is
+    pub fluid_viscosity: f32,
+    pub num_circles: u32,
+    pub num_circles_ui: f32,
+    pub gravity_enabled: bool,
+    /// how fast gravity accelerates the balls, in pixels per second squared
+    pub gravity_strength: f32,
+    /// which way gravity pulls, in degrees clockwise from the right (so 90 is straight down)
+    pub gravity_angle: f32,
+    /// whether gravity can be turned round with the arrow keys or the mouse
+    pub gravity_tilt: GravityTilt,
+    /// whether every ball attracts every other ball, like planets
+    pub nbody: bool,
+    /// the gravitational constant for `nbody`
+    pub nbody_strength: f32,
+    /// how coarse the Barnes-Hut approximation for `nbody` is; 0 is exact (and slow), around 1
+    /// is fast but rough
+    pub nbody_theta: f32,
+    /// stops the pull between two balls blowing up as they get very close
+    pub nbody_softening: f32,
+    /// whether charged balls push and pull each other
+    pub charges: bool,
+    /// the Coulomb constant for `charges`
+    pub charge_strength: f32,
+    /// how far away charged balls affect each other
+    pub charge_range: f32,
+    /// roughly what fraction of new balls are charged
+    pub charged_fraction: f32,
+    /// whether balls are split into species (by hue) that chase or flee each other
+    pub particle_life: bool,
+    /// how many species there are, up to `simulation::MAX_SPECIES`
+    pub particle_life_species: u32,
+    /// how far away balls can feel each other in particle life
+    pub particle_life_radius: f32,
+    pub particle_life_strength: f32,
+    /// row `i`, column `j` is how strongly species `i` is attracted to species `j`, from -1
+    /// (repelled) to 1
+    pub particle_life_matrix: Vec<Vec<f32>>,
+    /// how stiff the springs in newly spawned ropes, rings and blobs are
+    pub spring_stiffness: f32,
+    /// how quickly the springs in newly spawned soft bodies stop wobbling
+    pub spring_damping: f32,
+    pub draw_springs: bool,
+    /// the chance each sixtieth of a second that a ball touching a sticky one bonds to it, from
+    /// 0 (never) to 1
+    pub stickiness: f32,
+    /// whether every ball is sticky, rather than only the ones that have been made sticky or
+    /// have bonded to a sticky ball
+    pub all_sticky: bool,
+    /// how stiff the bonds between stuck together balls are
+    pub bond_stiffness: f32,
+    pub bond_damping: f32,
+    /// how far a bond can be stretched, as a fraction of its length, before it snaps
+    pub bond_break_stretch: f32,
+    /// how much speed balls keep after bouncing off an obstacle, from 0 to 1
+    pub obstacle_restitution: f32,
+    /// what happens to balls that reach each edge of the window (ignored by the starfield); an
+    /// edge opposite one that wraps wraps as well
+    pub boundary_left: BoundaryMode,
+    pub boundary_right: BoundaryMode,
+    pub boundary_top: BoundaryMode,
+    pub boundary_bottom: BoundaryMode,
+    /// how much speed balls keep after bouncing off an edge that reflects them, from 0 to 1
+    pub wall_restitution: f32,
+    /// whether balls age and expire once they've lived out their lifetime
+    pub lifetimes: bool,
+    /// each ball is given a random lifetime between these two, in seconds
+    pub min_lifetime: f32,
+    pub max_lifetime: f32,
+    /// whether balls fade out as they get older
+    pub age_fade: bool,
+    /// whether balls are drawn smaller as they get older
+    pub age_shrink: bool,
+    /// how far round the colour wheel a ball's hue turns over its life, from 0 (not at all) to
+    /// 1 (all the way round)
+    pub age_hue_shift: f32,
+    /// whether an expired ball is replaced with a new one (at the starfield origin, if the
+    /// starfield is on) or just removed
+    pub respawn_expired: bool,
+    pub particle_repel_force: f32,
+    pub allow_ball_intersection: bool,
+    /// how touching balls react to each other
+    pub collision_model: CollisionModel,
+    /// how much speed balls keep after bouncing off each other in the elastic model, from 0
+    /// (none) to 1 (all of it)
+    pub restitution: f32,
+    /// how much of the overlap between two balls the elastic model undoes each step
+    pub position_correction: f32,
+    /// whether balls that hit each other fast enough stick together as one bigger ball
+    pub merging: bool,
+    /// how fast two balls have to be coming together to merge; they can't close faster than
+    /// twice `max_velocity`
+    pub merge_speed: f32,
+    /// whether big balls that are hit hard enough break up into smaller ones
+    pub fragmenting: bool,
+    /// how fast two balls have to be coming together to break up; above
+    /// `merge_speed`, so gentler impacts merge and harder ones shatter
+    pub fragment_speed: f32,
+    /// the most pieces a ball breaks into, as long as none are smaller than `min_circle_size`
+    pub fragment_count: u32,
+    /// mass per unit of radius squared, so bigger balls are heavier
+    pub ball_density: f32,
+    pub draw_velocities: bool,
+    pub boids: bool,
+    /// how far away a boid can see its neighbours
+    pub boids_perception_radius: f32,
+    pub separation_distance: f32,
+    pub separation_weight: f32,
+    pub alignment_weight: f32,
+    pub cohesion_weight: f32,
+    pub avoid_walls_weight: f32,
+    pub avoid_walls_distance: f32,
+    pub boid_amount: f32,
+    pub max_velocity: f32,
+    pub starfield: bool,
+    pub warp_factor: f32,
+    pub monochrome: bool,
+    /// seed for the simulation's random number generator, from 0 to `MAX_SEED`; picked from the
+    /// clock if not set
+    pub seed: Option<u64>,
+    /// how many physics steps to run per second, independent of the frame rate
+    pub physics_hz: f32,
+    /// how positions and velocities are advanced each physics step
+    pub integrator: Integrator,
+    /// the most physics steps to run in a single frame before giving up on catching up
+    pub max_substeps: u32,
+    /// which spatial index to use for finding neighbouring balls
+    pub spatial_index: SpatialIndexKind,
+    /// size of each cell when `spatial_index` is the grid; about a ball's diameter works best
+    pub grid_cell_size: f32,
+    /// how many balls a quadtree leaf holds before it gets split up
+    pub quadtree_node_capacity: u32,
+    /// how deep the quadtree can get before leaves stop being split up
+    pub quadtree_max_depth: u32,
+    /// whether the quadtree grows to fit balls outside the window or clamps them to its edge
+    pub quadtree_out_of_bounds: OutOfBounds,
+}
+
+impl Default for JiggleBallsConfig {
+    // the default values for all the configurable stuff
+    fn default() -> Self {
+        JiggleBallsConfig {
+            min_circle_size: 5.0,
+            max_circle_size: 50.0,
+            audio_enabled: true,
+            is_fullscreen: false,
+            jiggle: 3.0,
+            jiggle_model: JiggleModel::Uniform,
+            diffusion: 200.0,
+            brownian_damping: 2.0,
+            thermal: false,
+            heat_conductivity: 2.0,
+            hot_wall: Wall::Bottom,
+            cold_wall: Wall::Top,
+            hot_temperature: 5.0,
+            cold_temperature: 0.0,
+            thermal_wall_depth: 50.0,
+            wall_heat_rate: 2.0,
+            color_by_temperature: false,
+            mouse_repel_force: 2.0,
+            mouse_attract_force: 0.15,
+            mouse_attract_distance: 100.0,
+            medium_viscosity: 100.0,
+            fluid: false,
+            fluid_smoothing_radius: 40.0,
+            fluid_rest_density: 0.005,
+            fluid_stiffness: 1000000.0,
+            fluid_viscosity: 20.0,
+            num_circles: 1000,
+            num_circles_ui: 1000.0,
+            gravity_enabled: false,
+            gravity_strength: 588.6,
+            gravity_angle: 90.0,
+            gravity_tilt: GravityTilt::Off,
+            nbody: false,
+            nbody_strength: 1000.0,
+            nbody_theta: 0.5,
+            nbody_softening: 10.0,
+            charges: false,
+            charge_strength: 500.0,
+            charge_range: 200.0,
+            charged_fraction: 0.5,
+            particle_life: false,
+            particle_life_species: 4,
+            particle_life_radius: 80.0,
+            particle_life_strength: 5.0,
+            particle_life_matrix: vec![
+                vec![0.8, 0.5, -0.3, 0.0],
+                vec![-0.5, 0.6, 0.4, -0.2],
+                vec![0.2, -0.6, 0.7, 0.5],
+                vec![0.5, 0.0, -0.4, 0.3],
+            ],
+            spring_stiffness: 5000.0,
+            spring_damping: 300.0,
+            draw_springs: true,
+            stickiness: 0.0,
+            all_sticky: false,
+            bond_stiffness: 10000.0,
+            bond_damping: 100.0,
+            bond_break_stretch: 0.5,
+            obstacle_restitution: 0.5,
+            boundary_left: BoundaryMode::Reflect,
+            boundary_right: BoundaryMode::Reflect,
+            boundary_top: BoundaryMode::Reflect,
+            boundary_bottom: BoundaryMode::Reflect,
+            wall_restitution: 0.5,
+            lifetimes: false,
+            min_lifetime: 5.0,
+            max_lifetime: 15.0,
+            age_fade: true,
+            age_shrink: false,
+            age_hue_shift: 0.0,
+            respawn_expired: true,
+            particle_repel_force: 30.0,
+            allow_ball_intersection: false,
+            collision_model: CollisionModel::SoftRepel,
+            restitution: 0.9,
+            position_correction: 0.8,
+            merging: false,
+            merge_speed: 40.0,
+            fragmenting: false,
+            fragment_speed: 80.0,
+            fragment_count: 4,
+            ball_density: 1.0,
+            draw_velocities: false,
+            boids: false,
+            boids_perception_radius: 5.0,
+            separation_distance: 10.0,
+            separation_weight: 1.0,
+            alignment_weight: 1.0,
+            cohesion_weight: 1.0,
+            avoid_walls_weight: 0.01,
+            avoid_walls_distance: 100.0,
+            boid_amount: 10.0,
+            max_velocity: 50.0,
+            starfield: false,
+            warp_factor: 1.0,
+            monochrome: false,
+            seed: None,
+            physics_hz: 60.0,
+            max_substeps: 5,
+            integrator: Integrator::ExplicitEuler,
+            spatial_index: SpatialIndexKind::Quadtree,
+            grid_cell_size: 100.0,
+            quadtree_node_capacity: 5,
+            quadtree_max_depth: 12,
+            quadtree_out_of_bounds: OutOfBounds::Grow,
+        }
+    }
+}
+
+/// Overwrite any settings in `config` with the values found in `config.toml`, if it exists.
+pub fn load_config(config: &mut JiggleBallsConfig) {
+    match fs::read_to_string("config.toml") {
+        Ok(config_str) => {
+            let loaded_config: HashMap<String, Value> = toml::from_str(config_str.as_str())
+                .map_err(|err| {
+                    println!("Could not read config file: {}", err);
+                })
+                .unwrap();
+            for (key, value) in loaded_config {
+                match key.as_str() {
+                    "min_circle_size" => {
+                        config.min_circle_size = value.try_into().unwrap();
+                    }
+                    "max_circle_size" => {
+                        config.max_circle_size = value.try_into().unwrap();
+                    }
+                    "audio_enabled" => {
+                        config.audio_enabled = value.as_bool().unwrap();
+                    }
+                    "is_fullscreen" => {
+                        config.is_fullscreen = value.as_bool().unwrap();
+                    }
+                    "jiggle" => {
+                        config.jiggle = value.try_into().unwrap();
+                    }
+                    "jiggle_model" => {
+                        config.jiggle_model = value.try_into().unwrap();
+                    }
+                    "diffusion" => {
+                        config.diffusion = value.try_into().unwrap();
+                    }
+                    "brownian_damping" => {
+                        config.brownian_damping = value.try_into().unwrap();
+                    }
+                    "thermal" => {
+                        config.thermal = value.as_bool().unwrap();
+                    }
+                    "heat_conductivity" => {
+                        config.heat_conductivity = value.try_into().unwrap();
+                    }
+                    "hot_wall" => {
+                        config.hot_wall = value.try_into().unwrap();
+                    }
+                    "cold_wall" => {
+                        config.cold_wall = value.try_into().unwrap();
+                    }
+                    "hot_temperature" => {
+                        config.hot_temperature = value.try_into().unwrap();
+                    }
+                    "cold_temperature" => {
+                        config.cold_temperature = value.try_into().unwrap();
+                    }
+                    "thermal_wall_depth" => {
+                        config.thermal_wall_depth = value.try_into().unwrap();
+                    }
+                    "wall_heat_rate" => {
+                        config.wall_heat_rate = value.try_into().unwrap();
+                    }
+                    "color_by_temperature" => {
+                        config.color_by_temperature = value.as_bool().unwrap();
+                    }
+                    "mouse_repel_force" => {
+                        config.mouse_repel_force = value.try_into().unwrap();
+                    }
+                    "mouse_attract_force" => {
+                        config.mouse_attract_force = value.try_into().unwrap();
+                    }
+                    "mouse_attract_distance" => {
+                        config.mouse_attract_distance = value.try_into().unwrap();
+                    }
+                    "medium_viscosity" => {
+                        config.medium_viscosity = value.try_into().unwrap();
+                    }
+                    "fluid" => {
+                        config.fluid = value.as_bool().unwrap();
+                    }
+                    "fluid_smoothing_radius" => {
+                        config.fluid_smoothing_radius = value.try_into().unwrap();
+                    }
+                    "fluid_rest_density" => {
+                        config.fluid_rest_density = value.try_into().unwrap();
+                    }
+                    "fluid_stiffness" => {
+                        config.fluid_stiffness = value.try_into().unwrap();
+                    }
+                    "fluid_viscosity" => {
+                        config.fluid_viscosity = value.try_into().unwrap();
+                    }
+                    "num_circles" => {
+                        config.num_circles = value.as_integer().unwrap() as u32;
+                    }
+                    "num_circles_ui" => {
+                        config.num_circles_ui = value.try_into().unwrap();
+                    }
+                    "gravity_enabled" => {
+                        config.gravity_enabled = value.as_bool().unwrap();
+                    }
+                    "gravity_strength" => {
+                        config.gravity_strength = value.try_into().unwrap();
+                    }
+                    "gravity_angle" => {
+                        config.gravity_angle = value.try_into().unwrap();
+                    }
+                    "gravity_tilt" => {
+                        config.gravity_tilt = value.try_into().unwrap();
+                    }
+                    "nbody" => {
+                        config.nbody = value.as_bool().unwrap();
+                    }
+                    "nbody_strength" => {
+                        config.nbody_strength = value.try_into().unwrap();
+                    }
+                    "nbody_theta" => {
+                        config.nbody_theta = value.try_into().unwrap();
+                    }
+                    "nbody_softening" => {
+                        config.nbody_softening = value.try_into().unwrap();
+                    }
+                    "charges" => {
+                        config.charges = value.as_bool().unwrap();
+                    }
+                    "charge_strength" => {
+                        config.charge_strength = value.try_into().unwrap();
+                    }
+                    "charge_range" => {
+                        config.charge_range = value.try_into().unwrap();
+                    }
+                    "charged_fraction" => {
+                        config.charged_fraction = value.try_into().unwrap();
+                    }
+                    "particle_life" => {
+                        config.particle_life = value.as_bool().unwrap();
+                    }
+                    "particle_life_species" => {
+                        config.particle_life_species = value.as_integer().unwrap() as u32;
+                    }
+                    "particle_life_radius" => {
+                        config.particle_life_radius = value.try_into().unwrap();
+                    }
+                    "particle_life_strength" => {
+                        config.particle_life_strength = value.try_into().unwrap();
+                    }
+                    "particle_life_matrix" => {
+                        config.particle_life_matrix = value.try_into().unwrap();
+                    }
+                    "spring_stiffness" => {
+                        config.spring_stiffness = value.try_into().unwrap();
+                    }
+                    "spring_damping" => {
+                        config.spring_damping = value.try_into().unwrap();
+                    }
+                    "draw_springs" => {
+                        config.draw_springs = value.as_bool().unwrap();
+                    }
+                    "stickiness" => {
+                        config.stickiness = value.try_into().unwrap();
+                    }
+                    "all_sticky" => {
+                        config.all_sticky = value.as_bool().unwrap();
+                    }
+                    "bond_stiffness" => {
+                        config.bond_stiffness = value.try_into().unwrap();
+                    }
+                    "bond_damping" => {
+                        config.bond_damping = value.try_into().unwrap();
+                    }
+                    "bond_break_stretch" => {
+                        config.bond_break_stretch = value.try_into().unwrap();
+                    }
+                    "obstacle_restitution" => {
+                        config.obstacle_restitution = value.try_into().unwrap();
+                    }
+                    "boundary_left" => {
+                        config.boundary_left = value.try_into().unwrap();
+                    }
+                    "boundary_right" => {
+                        config.boundary_right = value.try_into().unwrap();
+                    }
+                    "boundary_top" => {
+                        config.boundary_top = value.try_into().unwrap();
+                    }
+                    "boundary_bottom" => {
+                        config.boundary_bottom = value.try_into().unwrap();
+                    }
+                    "wall_restitution" => {
+                        config.wall_restitution = value.try_into().unwrap();
+                    }
+                    "lifetimes" => {
+                        config.lifetimes = value.as_bool().unwrap();
+                    }
+                    "min_lifetime" => {
+                        config.min_lifetime = value.try_into().unwrap();
+                    }
+                    "max_lifetime" => {
+                        config.max_lifetime = value.try_into().unwrap();
+                    }
+                    "age_fade" => {
+                        config.age_fade = value.as_bool().unwrap();
+                    }
+                    "age_shrink" => {
+                        config.age_shrink = value.as_bool().unwrap();
+                    }
+                    "age_hue_shift" => {
+                        config.age_hue_shift = value.try_into().unwrap();
+                    }
+                    "respawn_expired" => {
+                        config.respawn_expired = value.as_bool().unwrap();
+                    }
+                    "particle_repel_force" => {
+                        config.particle_repel_force = value.try_into().unwrap();
+                    }
+                    "allow_ball_intersection" => {
+                        config.allow_ball_intersection = value.as_bool().unwrap();
+                    }
+                    "collision_model" => {
+                        config.collision_model = value.try_into().unwrap();
+                    }
+                    "restitution" => {
+                        config.restitution = value.try_into().unwrap();
+                    }
+                    "position_correction" => {
+                        config.position_correction = value.try_into().unwrap();
+                    }
+                    "merging" => {
+                        config.merging = value.as_bool().unwrap();
+                    }
+                    "merge_speed" => {
+                        config.merge_speed = value.try_into().unwrap();
+                    }
+                    "fragmenting" => {
+                        config.fragmenting = value.as_bool().unwrap();
+                    }
+                    "fragment_speed" => {
+                        config.fragment_speed = value.try_into().unwrap();
+                    }
+                    "fragment_count" => {
+                        config.fragment_count = value.as_integer().unwrap() as u32;
+                    }
+                    "ball_density" => {
+                        config.ball_density = value.try_into().unwrap();
+                    }
+                    "draw_velocities" => {
+                        config.draw_velocities = value.as_bool().unwrap();
+                    }
+                    "boids" => {
+                        config.boids = value.as_bool().unwrap();
+                    }
+                    "boids_perception_radius" => {
+                        config.boids_perception_radius = value.try_into().unwrap();
+                    }
+                    // older configs gave the width of a square rather than a radius
+                    "boids_box_size" => {
+                        let box_size: f32 = value.try_into().unwrap();
+                        config.boids_perception_radius = box_size / 2.0;
+                    }
+                    "separation_distance" => {
+                        config.separation_distance = value.try_into().unwrap();
+                    }
+                    "separation_weight" => {
+                        config.separation_weight = value.try_into().unwrap();
+                    }
+                    "alignment_weight" => {
+                        config.alignment_weight = value.try_into().unwrap();
+                    }
+                    "cohesion_weight" => {
+                        config.cohesion_weight = value.try_into().unwrap();
+                    }
+                    "avoid_walls_weight" => {
+                        config.avoid_walls_weight = value.try_into().unwrap();
+                    }
+                    "avoid_walls_distance" => {
+                        config.avoid_walls_distance = value.try_into().unwrap();
+                    }
+                    "boid_amount" => {
+                        config.boid_amount = value.try_into().unwrap();
+                    }
+                    "max_velocity" => {
+                        config.max_velocity = value.try_into().unwrap();
+                    }
+                    "starfield" => {
+                        config.starfield = value.as_bool().unwrap();
+                    }
+                    "warp_factor" => {
+                        config.warp_factor = value.try_into().unwrap();
+                    }
+                    "monochrome" => {
+                        config.monochrome = value.as_bool().unwrap();
+                    }
+                    "seed" => match value.as_integer() {
+                        Some(seed) if seed >= 0 => config.seed = Some(seed as u64),
+                        _ => println!(
+                            "seed needs to be a whole number from 0 to {}, ignoring it",
+                            MAX_SEED
+                        ),
+                    },
+                    "physics_hz" => {
+                        config.physics_hz = value.try_into().unwrap();
+                    }
+                    "max_substeps" => {
+                        config.max_substeps = value.as_integer().unwrap() as u32;
+                    }
+                    "integrator" => {
+                        config.integrator = value.try_into().unwrap();
+                    }
+                    "spatial_index" => {
+                        config.spatial_index = value.try_into().unwrap();
+                    }
+                    "grid_cell_size" => {
+                        config.grid_cell_size = value.try_into().unwrap();
+                    }
+                    "quadtree_node_capacity" => {
+                        config.quadtree_node_capacity = value.as_integer().unwrap() as u32;
+                    }
+                    "quadtree_max_depth" => {
+                        config.quadtree_max_depth = value.as_integer().unwrap() as u32;
+                    }
+                    "quadtree_out_of_bounds" => {
+                        config.quadtree_out_of_bounds = value.try_into().unwrap();
+                    }
+                    _ => {
+                        println!("Unknown config key: {}", key);
+                    }
+                }
+            }
+        }
+        Err(err) => {
+            println!(
+                "No config file found, using default values (error was: {})",
+                err
+            );
+        }
+    }
+}
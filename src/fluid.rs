@@ -0,0 +1,36 @@
+//! Smoothing kernels for the smoothed-particle hydrodynamics (SPH) fluid mode, in which each ball
+//! is a blob of liquid spread over a disc of radius `smoothing_radius` around it.
+//! These are the usual 2D versions of the kernels from Müller et al., "Particle-Based Fluid
+//! Simulation for Interactive Applications", normalised so that they integrate to one.
+
+use std::f32::consts::PI;
+
+/// How much a ball adds to the density of the fluid `distance` away from it.
+pub fn density_kernel(distance: f32, smoothing_radius: f32) -> f32 {
+    if distance >= smoothing_radius {
+        return 0.0;
+    }
+    let h2 = smoothing_radius * smoothing_radius;
+    let falloff = h2 - distance * distance;
+    4.0 / (PI * h2.powi(4)) * falloff * falloff * falloff
+}
+
+/// How steeply the pressure kernel falls away `distance` from a ball; the pressure force acts
+/// along the line between two balls in proportion to this.  Unlike the density kernel's, its
+/// slope doesn't vanish as balls get close, so they're kept apart rather than clumping.
+pub fn pressure_kernel_slope(distance: f32, smoothing_radius: f32) -> f32 {
+    if distance >= smoothing_radius {
+        return 0.0;
+    }
+    let falloff = smoothing_radius - distance;
+    30.0 / (PI * smoothing_radius.powi(5)) * falloff * falloff
+}
+
+/// The Laplacian of the viscosity kernel, which weights how much two balls `distance` apart
+/// drag each other towards the same velocity.
+pub fn viscosity_kernel_laplacian(distance: f32, smoothing_radius: f32) -> f32 {
+    if distance >= smoothing_radius {
+        return 0.0;
+    }
+    40.0 / (PI * smoothing_radius.powi(5)) * (smoothing_radius - distance)
+}
@@ -0,0 +1,236 @@
+use crate::quadtree::Handle;
+use macroquad::prelude::*;
+
+/// marks the end of a list / a handle that isn't in the grid
+const NONE: u32 = u32::MAX;
+
+const MIN_BUCKETS: usize = 64;
+const MAX_BUCKETS: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy)]
+struct GridItem {
+    position: Vec2,
+    cell: (i32, i32),
+    /// the bucket this item is in, or NONE if the handle isn't in the grid
+    bucket: u32,
+    prev: u32,
+    next: u32,
+}
+
+impl GridItem {
+    const ABSENT: GridItem = GridItem {
+        position: Vec2::ZERO,
+        cell: (0, 0),
+        bucket: NONE,
+        prev: NONE,
+        next: NONE,
+    };
+}
+
+/// A uniform grid of square cells, hashed into a fixed number of buckets so that it doesn't
+/// matter how big the area is or whether balls stray outside it.  Since balls have a bounded
+/// radius, a cell size of about a ball's diameter means a neighbour search only has to look at
+/// a handful of cells.
+///
+/// Like the quadtree, each bucket is a linked list threaded through one slot per handle, so
+/// rebuilding and querying don't allocate once it has warmed up.
+#[derive(Debug, Clone)]
+pub struct SpatialHashGrid {
+    bounds: Rect,
+    cell_size: f32,
+    /// head of the list of items in each bucket
+    buckets: Vec<u32>,
+    items: Vec<GridItem>,
+    len: usize,
+}
+
+impl SpatialHashGrid {
+    pub fn new(bounds: Rect, cell_size: f32) -> Self {
+        let mut grid = SpatialHashGrid {
+            bounds,
+            cell_size: cell_size.max(1.0),
+            buckets: Vec::new(),
+            items: Vec::new(),
+            len: 0,
+        };
+        grid.clear(bounds);
+        grid
+    }
+
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /// Remove everything from the grid and change its bounds.  The number of buckets is picked
+    /// so that every cell inside the bounds can (hash collisions aside) have its own bucket.
+    pub fn clear(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+        let columns = (bounds.w / self.cell_size).ceil().max(1.0) as usize;
+        let rows = (bounds.h / self.cell_size).ceil().max(1.0) as usize;
+        let num_buckets = (columns * rows)
+            .next_power_of_two()
+            .clamp(MIN_BUCKETS, MAX_BUCKETS);
+        self.buckets.clear();
+        self.buckets.resize(num_buckets, NONE);
+        self.items.clear();
+        self.len = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.items
+            .get(handle)
+            .is_some_and(|item| item.bucket != NONE)
+    }
+
+    pub fn position(&self, handle: Handle) -> Option<Vec2> {
+        self.items
+            .get(handle)
+            .filter(|item| item.bucket != NONE)
+            .map(|item| item.position)
+    }
+
+    pub fn insert(&mut self, handle: Handle, position: Vec2) {
+        if self.contains(handle) {
+            self.remove(handle);
+        }
+        if handle >= self.items.len() {
+            self.items.resize(handle + 1, GridItem::ABSENT);
+        }
+        let cell = self.cell_for(position);
+        let bucket = self.bucket_for(cell);
+        let head = self.buckets[bucket as usize];
+        if head != NONE {
+            self.items[head as usize].prev = handle as u32;
+        }
+        self.items[handle] = GridItem {
+            position,
+            cell,
+            bucket,
+            prev: NONE,
+            next: head,
+        };
+        self.buckets[bucket as usize] = handle as u32;
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, handle: Handle) {
+        if !self.contains(handle) {
+            return;
+        }
+        let GridItem {
+            bucket, prev, next, ..
+        } = self.items[handle];
+        if prev == NONE {
+            self.buckets[bucket as usize] = next;
+        } else {
+            self.items[prev as usize].next = next;
+        }
+        if next != NONE {
+            self.items[next as usize].prev = prev;
+        }
+        self.items[handle] = GridItem::ABSENT;
+        self.len -= 1;
+    }
+
+    /// Move an item to a new position, which is cheap if it stays inside the same cell.
+    pub fn update(&mut self, handle: Handle, position: Vec2) {
+        if self.contains(handle) && self.items[handle].cell == self.cell_for(position) {
+            self.items[handle].position = position;
+            return;
+        }
+        self.insert(handle, position);
+    }
+
+    /// Every handle whose position lies inside `range`.
+    pub fn query(&self, range: Rect) -> GridQuery<'_> {
+        let min_cell = self.cell_for(range.point());
+        let max_cell = self.cell_for(range.point() + range.size());
+        let num_cells = (max_cell.0 as i64 - min_cell.0 as i64 + 1)
+            * (max_cell.1 as i64 - min_cell.1 as i64 + 1);
+        // a huge range touches more cells than there are buckets, so it's quicker to just look
+        // through every bucket once
+        let scan_buckets = num_cells > self.buckets.len() as i64;
+        GridQuery {
+            grid: self,
+            range,
+            min_cell,
+            max_cell,
+            cell: min_cell,
+            scan_buckets,
+            bucket: 0,
+            item: if scan_buckets {
+                self.buckets[0]
+            } else {
+                self.buckets[self.bucket_for(min_cell) as usize]
+            },
+        }
+    }
+
+    fn cell_for(&self, position: Vec2) -> (i32, i32) {
+        let relative = (position - self.bounds.point()) / self.cell_size;
+        (relative.x.floor() as i32, relative.y.floor() as i32)
+    }
+
+    fn bucket_for(&self, (x, y): (i32, i32)) -> u32 {
+        let hash = (x as u32).wrapping_mul(73856093) ^ (y as u32).wrapping_mul(19349663);
+        hash & (self.buckets.len() as u32 - 1)
+    }
+}
+
+/// Iterator over the handles inside a rectangle, visiting each cell the rectangle touches in
+/// turn (or each bucket, for very large rectangles).
+pub struct GridQuery<'a> {
+    grid: &'a SpatialHashGrid,
+    range: Rect,
+    min_cell: (i32, i32),
+    max_cell: (i32, i32),
+    cell: (i32, i32),
+    scan_buckets: bool,
+    bucket: usize,
+    item: u32,
+}
+
+impl Iterator for GridQuery<'_> {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Handle> {
+        loop {
+            while self.item != NONE {
+                let item = &self.grid.items[self.item as usize];
+                let handle = self.item as Handle;
+                self.item = item.next;
+                // buckets are shared between cells, so skip anything from a different cell
+                if (self.scan_buckets || item.cell == self.cell)
+                    && self.range.contains(item.position)
+                {
+                    return Some(handle);
+                }
+            }
+            if self.scan_buckets {
+                self.bucket += 1;
+                self.item = *self.grid.buckets.get(self.bucket)?;
+                continue;
+            }
+            if self.cell.0 < self.max_cell.0 {
+                self.cell.0 += 1;
+            } else if self.cell.1 < self.max_cell.1 {
+                self.cell = (self.min_cell.0, self.cell.1 + 1);
+            } else {
+                return None;
+            }
+            self.item = self.grid.buckets[self.grid.bucket_for(self.cell) as usize];
+        }
+    }
+}
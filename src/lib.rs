@@ -0,0 +1,121 @@
+//! The simulation side of Jiggle Balls: the balls themselves, the spatial indexes used to find
+//! neighbouring balls, the config and the [`simulation::Simulation`] that ties them together.
+//! None of this opens a window or plays any audio, so it can be driven from tests, benchmarks or
+//! other front ends; the `jiggleballs` binary is a thin macroquad app on top of it.
+pub mod config;
+pub mod fluid;
+pub mod grid;
+pub mod quadtree;
+pub mod rng;
+pub mod scene;
+pub mod simulation;
+pub mod spatial;
+pub mod springs;
+
+use macroquad::color::hsl_to_rgb;
+use macroquad::prelude::*;
+use rng::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Uniquely identifies a ball within a simulation, no matter how its state changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct CircleId(pub u64);
+
+/// A single ball.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Circle {
+    pub id: CircleId,
+    #[serde(with = "scene::vec2_array")]
+    pub position: Vec2,
+    pub radius: f32,
+    #[serde(with = "scene::color_array")]
+    pub color: Color,
+    #[serde(with = "scene::vec2_array")]
+    pub velocity: Vec2,
+    /// proportional to the area of the ball, and worked out again every step
+    #[serde(default)]
+    pub mass: f32,
+    /// electric charge: positive, negative or zero for a neutral ball
+    #[serde(default)]
+    pub charge: f32,
+    /// how long the ball has been alive for, in seconds, while lifetimes are on
+    #[serde(default)]
+    pub age: f32,
+    /// how old the ball can get before it expires, or 0 if it hasn't been given a lifetime yet
+    #[serde(default)]
+    pub lifetime: f32,
+    /// whether other balls bond to this one when they touch it
+    #[serde(default)]
+    pub sticky: bool,
+    /// how hot the ball is, which sets how hard it jiggles while the thermal model is on; a
+    /// ball at 1 jiggles by the configured `jiggle`
+    #[serde(default = "room_temperature")]
+    pub temperature: f32,
+}
+
+/// The temperature new balls start at.
+fn room_temperature() -> f32 {
+    1.0
+}
+
+impl Circle {
+    pub fn new(id: CircleId, position: Vec2, radius: f32, color: Color) -> Self {
+        Circle {
+            id,
+            position,
+            radius,
+            color,
+            velocity: Vec2::ZERO,
+            mass: radius * radius,
+            charge: 0.0,
+            age: 0.0,
+            lifetime: 0.0,
+            sticky: false,
+            temperature: room_temperature(),
+        }
+    }
+
+    /// How far through its life the ball is, from 0 (newborn) to 1 (about to expire); always 0
+    /// for a ball without a lifetime.
+    pub fn life_fraction(&self) -> f32 {
+        if self.lifetime > 0.0 {
+            (self.age / self.lifetime).min(1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+pub fn bounding_box(width: f32, height: f32) -> Rect {
+    Rect::new(0.0, 0.0, width, height)
+}
+
+/// Generate a stationary ball with a random position inside `width`x`height`, a random size
+/// between `min_size` and `max_size` and a random hue.
+///
+/// Roughly `charged_fraction` of balls are given a charge proportional to their area, positive
+/// for the warmer half of the hues and negative for the cooler half, so that colour groups
+/// attract or repel each other; the rest are neutral.
+pub fn gen_circle(
+    rng: &mut Rng,
+    id: CircleId,
+    width: f32,
+    height: f32,
+    min_size: f32,
+    max_size: f32,
+    charged_fraction: f32,
+) -> Circle {
+    let x = rng.gen_range(0.0, width);
+    let y = rng.gen_range(0.0, height);
+    let mut h = rng.gen_range(0.0, 100.0);
+    h /= 100.0;
+    let color = hsl_to_rgb(h, 0.5, 0.5);
+    let circle_size = rng.gen_range(min_size, max_size);
+    let mut circle = Circle::new(id, vec2(x, y), circle_size, color);
+    // only roll for a charge when there's a chance of one, so uncharged runs replay the same
+    if charged_fraction > 0.0 && rng.gen_range(0.0, 1.0) < charged_fraction {
+        let sign = if h < 0.5 { 1.0 } else { -1.0 };
+        circle.charge = sign * circle_size * circle_size;
+    }
+    circle
+}
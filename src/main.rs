@@ -1,25 +1,31 @@
 #![windows_subsystem = "windows"]
-mod quadtree;
-use crate::quadtree::Quadtree;
 use futures::executor;
 use hound::WavSpec;
+use jiggleballs::config::{load_config, JiggleBallsConfig};
+use jiggleballs::rng::MAX_SEED;
+use jiggleballs::scene::{load_scene, save_scene, Emitter, Obstacle, Portal, Sink};
+use jiggleballs::simulation::{
+    BoundaryMode, CollisionModel, GravityTilt, InputState, Integrator, JiggleModel, Simulation,
+    SimulationEvent, Wall, MAX_SPECIES,
+};
+use jiggleballs::spatial::SpatialIndex;
+use jiggleballs::Circle;
 use macroquad::audio::{load_sound_from_bytes, play_sound_once};
-use macroquad::color::hsl_to_rgb;
 use macroquad::hash;
 use macroquad::prelude::*;
 use macroquad::ui::root_ui;
 use macroquad::ui::widgets::Window;
 use miniquad::window::screen_size;
-use quad_rand::{rand, srand, RandomRange};
-use serde::{Deserialize, Serialize};
+use quad_rand::{rand, srand};
 use std::collections::HashMap;
 use std::fmt::Write;
 use std::fs;
 use std::io::{BufWriter, Cursor};
-use toml::Value;
 
 const DEFAULT_WIDTH: f32 = 1920.0;
 const DEFAULT_HEIGHT: f32 = 1080.0;
+/// how big the ends of a portal drawn with the mouse are
+const PORTAL_RADIUS: f32 = 25.0;
 
 macro_rules! toast_hash {
     ($($x:expr),*) => {{
@@ -27,9 +33,6 @@ macro_rules! toast_hash {
     }};
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-struct Circle(f32, f32, f32, Color, Vec2);
-
 trait RoundToDigits {
     fn round_to_digits(self, digits: u32) -> Self;
 }
@@ -41,14 +44,6 @@ impl RoundToDigits for f32 {
     }
 }
 
-fn get_random_value<T: RandomRange>(min: T, max: T) -> T {
-    T::gen_range(min, max)
-}
-
-fn bounding_box(width: f32, height: f32) -> Rect {
-    Rect::new(0.0, 0.0, width, height)
-}
-
 fn beep(beep_note: f32, beep_duration: f32) {
     let beep_freq = 440.0 * 2.0f32.powf(beep_note / 12.0);
     let mut sound = gen_sine_wave(beep_freq, 44100, beep_duration);
@@ -69,32 +64,6 @@ fn conf() -> Conf {
     }
 }
 
-fn gen_circle(width: f32, height: f32, min_size: f32, max_size: f32) -> Circle {
-    let x = get_random_value(0.0, width);
-    let y = get_random_value(0.0, height);
-    let mut h = get_random_value(0.0, 100.0);
-    h /= 100.0;
-    let color = hsl_to_rgb(h, 0.5, 0.5);
-    let circle_size = get_random_value(min_size, max_size);
-    let velocity = Vec2::new(0.0, 0.0);
-    Circle(x, y, circle_size, color, velocity)
-}
-
-fn reset_circles(
-    circles: &mut Vec<Box<Circle>>,
-    num_circles: u32,
-    width: f32,
-    height: f32,
-    min_size: f32,
-    max_size: f32,
-) {
-    circles.clear();
-    for _i in 0..num_circles {
-        let circle = Box::new(gen_circle(width, height, min_size, max_size));
-        circles.push(circle);
-    }
-}
-
 fn raw_to_wave(raw: &[f32], channels: u16) -> Vec<u8> {
     let spec = WavSpec {
         channels,
@@ -129,143 +98,151 @@ fn gen_sine_wave(freq: f32, sample_rate: u32, duration: f32) -> Vec<f32> {
     samples
 }
 
-#[derive(Deserialize, Serialize, Clone)]
-struct JiggleBallsConfig {
-    min_circle_size: f32,
-    max_circle_size: f32,
-    audio_enabled: bool,
-    is_fullscreen: bool,
-    jiggle: f32,
-    mouse_repel_force: f32,
-    mouse_attract_force: f32,
-    mouse_attract_distance: f32,
-    medium_viscosity: f32,
-    num_circles: u32,
-    num_circles_ui: f32,
-    gravity_enabled: bool,
-    particle_repel_force: f32,
-    allow_ball_intersection: bool,
-    draw_velocities: bool,
-    boids: bool,
-    boids_box_size: f32,
-    separation_distance: f32,
-    separation_weight: f32,
-    alignment_weight: f32,
-    cohesion_weight: f32,
-    avoid_walls_weight: f32,
-    avoid_walls_distance: f32,
-    boid_amount: f32,
-    max_velocity: f32,
-    starfield: bool,
-    warp_factor: f32,
-    monochrome: bool,
+/// What dragging the mouse draws.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DrawTool {
+    /// the mouse pushes and pulls the balls as usual
+    Off,
+    Segment,
+    Circle,
+    Box,
+    Emitter,
+    Sink,
+    Portal,
 }
 
-fn load_config (config: &mut JiggleBallsConfig) {
-    match fs::read_to_string("config.toml") {
-        Ok(config_str) => {
-            let loaded_config: HashMap<String, Value> = toml::from_str(config_str.as_str())
-                .map_err(|err| {
-                    println!("Could not read config file: {}", err);
-                })
-                .unwrap();
-            for (key, value) in loaded_config {
-                match key.as_str() {
-                    "min_circle_size" => {
-                        config.min_circle_size = value.try_into().unwrap();
-                    }
-                    "max_circle_size" => {
-                        config.max_circle_size = value.try_into().unwrap();
-                    }
-                    "audio_enabled" => {
-                        config.audio_enabled = value.as_bool().unwrap();
-                    }
-                    "is_fullscreen" => {
-                        config.is_fullscreen = value.as_bool().unwrap();
-                    }
-                    "jiggle" => {
-                        config.jiggle = value.try_into().unwrap();
-                    }
-                    "mouse_repel_force" => {
-                        config.mouse_repel_force = value.try_into().unwrap();
-                    }
-                    "mouse_attract_force" => {
-                        config.mouse_attract_force = value.try_into().unwrap();
-                    }
-                    "mouse_attract_distance" => {
-                        config.mouse_attract_distance = value.try_into().unwrap();
-                    }
-                    "medium_viscosity" => {
-                        config.medium_viscosity = value.try_into().unwrap();
-                    }
-                    "num_circles" => {
-                        config.num_circles = value.as_integer().unwrap() as u32;
-                    }
-                    "num_circles_ui" => {
-                        config.num_circles_ui = value.try_into().unwrap();
-                    }
-                    "gravity_enabled" => {
-                        config.gravity_enabled = value.as_bool().unwrap();
-                    }
-                    "particle_repel_force" => {
-                        config.particle_repel_force = value.try_into().unwrap();
-                    }
-                    "allow_ball_intersection" => {
-                        config.allow_ball_intersection = value.as_bool().unwrap();
-                    }
-                    "draw_velocities" => {
-                        config.draw_velocities = value.as_bool().unwrap();
-                    }
-                    "boids" => {
-                        config.boids = value.as_bool().unwrap();
-                    }
-                    "boids_box_size" => {
-                        config.boids_box_size = value.try_into().unwrap();
-                    }
-                    "separation_distance" => {
-                        config.separation_distance = value.try_into().unwrap();
-                    }
-                    "separation_weight" => {
-                        config.separation_weight = value.try_into().unwrap();
-                    }
-                    "alignment_weight" => {
-                        config.alignment_weight = value.try_into().unwrap();
-                    }
-                    "cohesion_weight" => {
-                        config.cohesion_weight = value.try_into().unwrap();
-                    }
-                    "avoid_walls_weight" => {
-                        config.avoid_walls_weight = value.try_into().unwrap();
-                    }
-                    "avoid_walls_distance" => {
-                        config.avoid_walls_distance = value.try_into().unwrap();
-                    }
-                    "boid_amount" => {
-                        config.boid_amount = value.try_into().unwrap();
-                    }
-                    "max_velocity" => {
-                        config.max_velocity = value.try_into().unwrap();
-                    }
-                    "starfield" => {
-                        config.starfield = value.as_bool().unwrap();
-                    }
-                    "warp_factor" => {
-                        config.warp_factor = value.try_into().unwrap();
-                    }
-                    "monochrome" => {
-                        config.monochrome = value.as_bool().unwrap();
-                    }
-                    _ => {
-                        println!("Unknown config key: {}", key);
-                    }
+/// Anything the drawing tool can add to the scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SceneItem {
+    Obstacle(Obstacle),
+    Emitter(Emitter),
+    Sink(Sink),
+    Portal(Portal),
+}
+
+impl DrawTool {
+    fn next(self) -> Self {
+        match self {
+            DrawTool::Off => DrawTool::Segment,
+            DrawTool::Segment => DrawTool::Circle,
+            DrawTool::Circle => DrawTool::Box,
+            DrawTool::Box => DrawTool::Emitter,
+            DrawTool::Emitter => DrawTool::Sink,
+            DrawTool::Sink => DrawTool::Portal,
+            DrawTool::Portal => DrawTool::Off,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            DrawTool::Off => "off",
+            DrawTool::Segment => "wall",
+            DrawTool::Circle => "circle",
+            DrawTool::Box => "box (scroll to rotate)",
+            DrawTool::Emitter => "emitter (drag to aim)",
+            DrawTool::Sink => "sink",
+            DrawTool::Portal => "portal (drag between the ends)",
+        }
+    }
+
+    /// The item made by dragging from `start` to `end`, if it isn't too small to see.
+    fn item(self, start: Vec2, end: Vec2, angle: f32) -> Option<SceneItem> {
+        if start.distance(end) < 2.0 {
+            return None;
+        }
+        let offset = end - start;
+        match self {
+            DrawTool::Off => None,
+            DrawTool::Segment => Some(SceneItem::Obstacle(Obstacle::Segment { start, end })),
+            DrawTool::Circle => Some(SceneItem::Obstacle(Obstacle::Circle {
+                center: start,
+                radius: offset.length(),
+            })),
+            DrawTool::Box => Some(SceneItem::Obstacle(Obstacle::Box {
+                center: (start + end) / 2.0,
+                half_size: offset.abs() / 2.0,
+                angle,
+            })),
+            // the further the drag, the faster the balls come out
+            DrawTool::Emitter => Some(SceneItem::Emitter(Emitter {
+                position: start,
+                direction: offset.y.atan2(offset.x).to_degrees(),
+                speed: offset.length() * 4.0,
+                ..Default::default()
+            })),
+            DrawTool::Sink => Some(SceneItem::Sink(Sink {
+                center: start,
+                radius: offset.length(),
+            })),
+            DrawTool::Portal => Some(SceneItem::Portal(Portal {
+                a: start,
+                b: end,
+                radius: PORTAL_RADIUS,
+            })),
+        }
+    }
+}
+
+impl SceneItem {
+    fn add_to(self, sim: &mut Simulation) {
+        match self {
+            SceneItem::Obstacle(obstacle) => sim.obstacles.push(obstacle),
+            SceneItem::Emitter(emitter) => sim.emitters.push(emitter),
+            SceneItem::Sink(sink) => sim.sinks.push(sink),
+            SceneItem::Portal(portal) => sim.portals.push(portal),
+        }
+    }
+
+    fn draw(&self, color: Color) {
+        match self {
+            SceneItem::Obstacle(obstacle) => draw_obstacle(obstacle, color),
+            SceneItem::Emitter(emitter) => {
+                let tip = emitter.position
+                    + Vec2::from_angle(emitter.direction.to_radians()) * emitter.speed / 4.0;
+                draw_circle(emitter.position.x, emitter.position.y, 6.0, color);
+                draw_line(
+                    emitter.position.x,
+                    emitter.position.y,
+                    tip.x,
+                    tip.y,
+                    2.0,
+                    color,
+                );
+            }
+            SceneItem::Sink(sink) => {
+                draw_circle(
+                    sink.center.x,
+                    sink.center.y,
+                    sink.radius,
+                    Color::new(0.0, 0.0, 0.0, 0.6),
+                );
+                draw_circle_lines(sink.center.x, sink.center.y, sink.radius, 2.0, color);
+            }
+            SceneItem::Portal(portal) => {
+                for end in [portal.a, portal.b] {
+                    draw_circle_lines(end.x, end.y, portal.radius, 3.0, color);
                 }
+                draw_line(
+                    portal.a.x,
+                    portal.a.y,
+                    portal.b.x,
+                    portal.b.y,
+                    1.0,
+                    Color { a: 0.3, ..color },
+                );
             }
         }
-        Err(err) => {
-            println!(
-                "No config file found, using default values (error was: {})",
-                err
-            );
+    }
+}
+
+fn draw_obstacle(obstacle: &Obstacle, color: Color) {
+    match *obstacle {
+        Obstacle::Segment { start, end } => draw_line(start.x, start.y, end.x, end.y, 3.0, color),
+        Obstacle::Circle { center, radius } => draw_circle(center.x, center.y, radius, color),
+        Obstacle::Box { .. } => {
+            let corners = obstacle.corners();
+            draw_triangle(corners[0], corners[1], corners[2], color);
+            draw_triangle(corners[0], corners[2], corners[3], color);
         }
     }
 }
@@ -277,44 +254,31 @@ async fn main() {
     let mut show_gui = false;
     let mut show_debug_gui = false;
     let mut mouse_last_frame = vec2(0.0, 0.0);
+    let mut draw_tool = DrawTool::Off;
+    // where the mouse was pressed, while an obstacle is being drawn
+    let mut drag_start: Option<Vec2> = None;
+    // the angle of the box being drawn, changed with the mouse wheel
+    let mut drag_angle = 0.0;
 
-    let mut circles = Vec::new();
-    let mut circles_quadtree = Quadtree::new(Rect::new(0.0, 0.0, width, height));
-
-    // the default values for all the configurable stuff
-    let mut config = JiggleBallsConfig {
-        min_circle_size: 5.0,
-        max_circle_size: 50.0,
-        audio_enabled: true,
-        is_fullscreen: false,
-        jiggle: 3.0,
-        mouse_repel_force: 2.0,
-        mouse_attract_force: 0.15,
-        mouse_attract_distance: 100.0,
-        medium_viscosity: 100.0,
-        num_circles: 1000,
-        num_circles_ui: 1000.0,
-        gravity_enabled: false,
-        particle_repel_force: 30.0,
-        allow_ball_intersection: false,
-        draw_velocities: false,
-        boids: false,
-        boids_box_size: 10.0,
-        separation_distance: 10.0,
-        separation_weight: 1.0,
-        alignment_weight: 1.0,
-        cohesion_weight: 1.0,
-        avoid_walls_weight: 0.01,
-        avoid_walls_distance: 100.0,
-        boid_amount: 10.0,
-        max_velocity: 50.0,
-        starfield: false,
-        warp_factor: 1.0,
-        monochrome: false,
-    };
+    let mut config = JiggleBallsConfig::default();
 
     load_config(&mut config);
 
+    // command line flags take priority over the config file
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => match args.next().map(|seed| seed.parse::<u64>()) {
+                Some(Ok(seed)) if seed <= MAX_SEED => config.seed = Some(seed),
+                _ => println!(
+                    "--seed needs a whole number from 0 to {}, ignoring it",
+                    MAX_SEED
+                ),
+            },
+            _ => println!("Unknown argument: {}", arg),
+        }
+    }
+
     let ui_font = load_ttf_font("OfficeCodePro-Regular.ttf")
         .await
         .expect("Could not load UI font");
@@ -326,18 +290,7 @@ async fn main() {
         (width, height) = screen_size();
         request_new_screen_size(width, height);
     }
-    reset_circles(
-        &mut circles,
-        config.num_circles,
-        width,
-        height,
-        config.min_circle_size,
-        config.max_circle_size,
-    );
-    circles_quadtree.clear(bounding_box(width, height));
-    for circ in circles.clone() {
-        circles_quadtree.insert(circ.clone());
-    }
+    let mut sim = Simulation::new(config, width, height);
     let hud_textparams = TextParams {
         font: Some(&ui_font),
         font_size: 32,
@@ -347,41 +300,27 @@ async fn main() {
 
     let mut toast_messages: HashMap<u64, (u32, String)> = HashMap::new();
 
-    let mut starfield_origin = vec2(width / 2.0, height / 2.0);
-
     loop {
-        let delta_time = get_frame_time();
-        config.num_circles_ui = config.num_circles_ui.floor();
-        config.num_circles = config.num_circles_ui as u32;
-        if config.min_circle_size > config.max_circle_size {
-            config.min_circle_size = config.max_circle_size;
-        }
-        if config.max_circle_size < config.min_circle_size {
-            config.max_circle_size = config.min_circle_size;
-        }
-        config.min_circle_size = config.min_circle_size.round_to_digits(1);
-        config.max_circle_size = config.max_circle_size.round_to_digits(1);
-
-        if circles.len() < config.num_circles.try_into().unwrap() {
-            for _ in 1..config.num_circles - circles.len() as u32 {
-                let circle = Box::new(gen_circle(
-                    width,
-                    height,
-                    config.min_circle_size,
-                    config.max_circle_size,
-                ));
-                circles_quadtree.insert(circle.clone());
-                circles.push(circle);
-            }
-        } else if circles.len() > config.num_circles.try_into().unwrap() {
-            circles.drain((config.num_circles as usize)..);
+        let frame_time = get_frame_time();
+        sim.config.num_circles_ui = sim.config.num_circles_ui.floor();
+        sim.config.num_circles = sim.config.num_circles_ui as u32;
+        if sim.config.min_circle_size > sim.config.max_circle_size {
+            sim.config.min_circle_size = sim.config.max_circle_size;
         }
+        if sim.config.max_circle_size < sim.config.min_circle_size {
+            sim.config.max_circle_size = sim.config.min_circle_size;
+        }
+        sim.config.min_circle_size = sim.config.min_circle_size.round_to_digits(1);
+        sim.config.max_circle_size = sim.config.max_circle_size.round_to_digits(1);
+
+        sim.sync_circle_count();
 
         // truncate some of the floats that deal with pixel values so they're more realistic
-        config.jiggle = config.jiggle.trunc();
-        config.mouse_attract_distance = config.mouse_attract_distance.trunc();
+        sim.config.jiggle = sim.config.jiggle.trunc();
+        sim.config.mouse_attract_distance = sim.config.mouse_attract_distance.trunc();
 
         (width, height) = screen_size();
+        sim.resize(width, height);
         if !show_gui {
             show_mouse(false);
         } else {
@@ -389,11 +328,11 @@ async fn main() {
         }
 
         if is_key_pressed(KeyCode::Minus) {
-            config.jiggle -= 1.0;
+            sim.config.jiggle -= 1.0;
         }
 
         if is_key_pressed(KeyCode::Equal) {
-            config.jiggle += 1.0;
+            sim.config.jiggle += 1.0;
         }
 
         if is_key_pressed(KeyCode::Q) {
@@ -401,29 +340,44 @@ async fn main() {
         }
 
         if is_key_pressed(KeyCode::F) {
-            config.is_fullscreen = !config.is_fullscreen;
-            set_fullscreen(config.is_fullscreen);
+            sim.config.is_fullscreen = !sim.config.is_fullscreen;
+            set_fullscreen(sim.config.is_fullscreen);
             // maybe not needed?
             request_new_screen_size(width, height);
         }
 
-        if is_key_pressed(KeyCode::S) {
-            let config_str = toml::to_string(&config)
-                .map_err(|err| {
-                    println!("Could not serialize config: {}", err);
-                })
-                .unwrap();
-            let _ = fs::write("config.toml", config_str.as_str()).map_err(|err| {
-                println!("Could not save config: {}", err);
+        let shift_down = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        if shift_down && is_key_pressed(KeyCode::S) {
+            save_scene(&sim.scene(), "scene.toml");
+            toast_messages.insert(hash!(), (60, "Saved scene".to_string()));
+        } else if is_key_pressed(KeyCode::S) {
+            let config_str = toml::to_string(&sim.config).map_err(|err| {
+                println!("Could not serialize config: {}", err);
             });
-            toast_messages.insert(hash!(), (60, "Saved config".to_string()));
+            if let Ok(config_str) = config_str {
+                let _ = fs::write("config.toml", config_str.as_str()).map_err(|err| {
+                    println!("Could not save config: {}", err);
+                });
+                toast_messages.insert(hash!(), (60, "Saved config".to_string()));
+            }
         }
 
-        if is_key_pressed(KeyCode::L) {
-            load_config(&mut config);
+        if shift_down && is_key_pressed(KeyCode::L) {
+            if let Some(scene) = load_scene("scene.toml") {
+                sim.load_scene(scene);
+                toast_messages.insert(hash!(), (60, "Loaded scene".to_string()));
+            }
+        } else if is_key_pressed(KeyCode::L) {
+            load_config(&mut sim.config);
             toast_messages.insert(hash!(), (60, "Loaded config".to_string()));
         }
 
+        if is_key_pressed(KeyCode::T) {
+            draw_tool = draw_tool.next();
+            drag_start = None;
+            toast_messages.insert(hash!(), (60, format!("Drawing tool: {}", draw_tool.name())));
+        }
+
         if is_key_pressed(KeyCode::G) {
             show_gui = !show_gui;
         }
@@ -432,296 +386,188 @@ async fn main() {
             show_debug_gui = !show_debug_gui;
         }
 
-        if is_key_pressed(KeyCode::R) {
-            reset_circles(
-                &mut circles,
-                config.num_circles,
-                width,
-                height,
-                config.min_circle_size,
-                config.max_circle_size,
+        // soft bodies are spawned at the mouse, out of balls the size of the smallest ones
+        let (mouse_x, mouse_y) = mouse_position();
+        let body_ball_size = sim.config.min_circle_size.max(4.0);
+        if is_key_pressed(KeyCode::J) {
+            sim.spawn_blob(vec2(mouse_x, mouse_y), body_ball_size * 6.0, body_ball_size);
+        }
+        if is_key_pressed(KeyCode::O) {
+            sim.spawn_ring(vec2(mouse_x, mouse_y), body_ball_size * 8.0, body_ball_size);
+        }
+        if is_key_pressed(KeyCode::P) {
+            sim.spawn_rope(
+                vec2(mouse_x, mouse_y),
+                vec2(mouse_x + body_ball_size * 30.0, mouse_y),
+                body_ball_size,
             );
-            circles_quadtree.clear(bounding_box(width, height));
-            for circ in circles.clone() {
-                circles_quadtree.insert(circ.clone());
-            }
-            starfield_origin = vec2(width / 2.0, height / 2.0);
-            toast_messages.insert(toast_hash!(), (60, "Reset".to_string()));
         }
 
-        clear_background(Color::from_rgba(0x00, 0x00, 0x00, 0xC0));
-        let (mouse_x, mouse_y) = mouse_position();
-
-        // mouse control for starfield
-        if config.starfield {
-            // when the user drags the mouse, move the starfield in the opposite direction,
-            // by moving the starfield origin point
-            if is_mouse_button_down(MouseButton::Left) {
-                let mouse_x_dist = mouse_x - mouse_last_frame.x;
-                let mouse_y_dist = mouse_y - mouse_last_frame.y;
-                starfield_origin -= vec2(mouse_x_dist, mouse_y_dist) * delta_time * 100.0;
+        // draw scene items by dragging with the left button, delete them with the right
+        if draw_tool != DrawTool::Off && !show_gui {
+            let mouse = vec2(mouse_x, mouse_y);
+            if is_mouse_button_pressed(MouseButton::Left) {
+                drag_start = Some(mouse);
+                drag_angle = 0.0;
             }
-        }
-
-        circles = circles
-            .iter()
-            .map(|circ| {
-                let Circle(mut x, mut y, circle_size, color, velocity) = **circ;
-                // draw the circle before doing anything else - everything else is setting up for the
-                // next frame
-                if !config.allow_ball_intersection {
-                    let query_range = Rect::new(x - 50.0, y - 50.0, 100.0, 100.0);
-                    let results = circles_quadtree.query(query_range);
-                    for other in results {
-                        if other == *circ {
-                            continue;
-                        }
-                        // sqrt (pow(abs(other_x - x), 2) + pow(abs(other_y - y), 2))
-                        let Circle(other_x, other_y, other_size, _, _) = *other;
-                        let dist = vec2(other_x, other_y).distance(vec2(x, y));
-                        if dist < (circle_size + other_size) {
-                            let x_dist = other_x - x;
-                            let y_dist = other_y - y;
-                            x -= x_dist / 2.0;
-                            y -= y_dist / 2.0;
-                        }
-                    }
-                }
-                if config.monochrome {
-                    draw_circle(x, y, circle_size, WHITE);
-                } else {
-                    draw_circle(x, y, circle_size, color);
-                }
-
-                if config.draw_velocities {
-                    let Vec2 {
-                        x: next_x,
-                        y: next_y,
-                    } = vec2(x, y) + velocity;
-                    let mut color = RED;
-                    if config.monochrome {
-                        color = WHITE;
-                    }
-                    draw_line(x, y, next_x, next_y, 1.0, color);
-                }
-
-
-                let jiggle_x: f32 = get_random_value(-(config.jiggle), config.jiggle);
-                let jiggle_y: f32 = get_random_value(-(config.jiggle), config.jiggle);
-                let mut new_x = x;
-                let mut new_y = y;
-                let mut new_velocity = velocity.clone();
-
-                if config.gravity_enabled {
-                    new_velocity.y += 9.81;
-                }
-
-                let mut new_pos = vec2(new_x, new_y);
-                new_pos += velocity * delta_time;
-                if !config.starfield && velocity.x != 0.0 || velocity.y != 0.0 {
-                    new_velocity -= velocity.normalize() * delta_time * config.medium_viscosity;
-                }
-
-                if new_velocity.x < 0.01 && new_velocity.x > -0.01 {
-                    new_velocity.x = 0.0;
-                }
-                if new_velocity.y < 0.01 && new_velocity.y > -0.01 {
-                    new_velocity.y = 0.0;
-                }
-
-                // if starfield is enabled, move away from the center of the screen
-                if config.starfield {
-                    let from_center = vec2(x, y) - starfield_origin;
-                    let dist = from_center.length();
-                    if dist < circle_size {
-                        // pick a random direction to move in if you're really close to the center
-                        let mut rand_dir = vec2(0.0, 0.0);
-                        while rand_dir.x == 0.0 && rand_dir.y == 0.0 {
-                            rand_dir = vec2(
-                                get_random_value(-1.0, 1.0),
-                                get_random_value(-1.0, 1.0),
-                            );
-                        }
-                        new_velocity = rand_dir * delta_time * 10_f32.powf(config.warp_factor / 2.0);
-                    } else {
-                        // move away from the center
-                        new_velocity = from_center * delta_time * 10_f32.powf(config.warp_factor / 2.0);
-                    }
+            let wheel = mouse_wheel().1;
+            if wheel != 0.0 {
+                drag_angle += wheel.signum() * 5.0;
+            }
+            if is_mouse_button_released(MouseButton::Left) {
+                if let Some(item) =
+                    drag_start.and_then(|start| draw_tool.item(start, mouse, drag_angle))
+                {
+                    item.add_to(&mut sim);
                 }
+                drag_start = None;
+            }
+            if is_mouse_button_pressed(MouseButton::Right) {
+                sim.obstacles
+                    .retain(|obstacle| obstacle.contact(mouse, 1.0).is_none());
+                sim.emitters
+                    .retain(|emitter| emitter.position.distance(mouse) > 10.0);
+                sim.sinks.retain(|sink| !sink.swallows(mouse));
+                sim.portals.retain(|portal| {
+                    portal.a.distance(mouse) > portal.radius
+                        && portal.b.distance(mouse) > portal.radius
+                });
+            }
+        }
 
-                if config.boids {
-                    let query_range = Rect::new(
-                        x - (config.boids_box_size / 2.0),
-                        y - (config.boids_box_size / 2.0),
-                        config.boids_box_size,
-                        config.boids_box_size,
-                    );
-                    let results = circles_quadtree.query(query_range);
-                    if results.len() > 0 {
-                        let mut separation = Vec2::default();
-                        let mut alignment = Vec2::default();
-                        let mut cohesion = Vec2::default();
-
-                        for other in &results {
-                            if *other == *circ {
-                                continue; // Skip self
-                            }
-
-                            let Circle(other_x, other_y, _, _, other_velocity) = **other;
-                            let to_other = vec2(other_x, other_y) - vec2(x, y);
-
-                            // Separation: Move away from close neighbors
-                            if to_other.length() < config.separation_distance {
-                                separation -= to_other.normalize();
-                            }
-
-                            // Alignment: Align with the average velocity of neighbors
-                            alignment += other_velocity;
-
-                            // Cohesion: Move towards the average position of neighbors
-                            cohesion += vec2(other_x, other_y);
-                        }
-
-                        if results.len() > 1 {
-                            // turn alignment and cohesion into mean averages of position/velocity
-                            alignment /= (results.len() - 1) as f32;
-                            cohesion /= (results.len() - 1) as f32;
-                            separation /= (results.len() - 1) as f32;
-                        }
-                        let mut avoid_walls = vec2(0.0, 0.0);
-                        if x < config.avoid_walls_distance
-                            || x > width - config.avoid_walls_distance
-                            || y < config.avoid_walls_distance
-                            || y > height - config.avoid_walls_distance
-                        {
-                            avoid_walls = vec2(width / 2.0, height / 2.0) - vec2(x, y);
-                            avoid_walls *= config.avoid_walls_weight;
-                        }
-                        separation *= config.separation_weight;
-                        alignment *= config.alignment_weight;
-                        cohesion = (cohesion - vec2(x, y)).normalize() * config.cohesion_weight;
-
-                        new_velocity += (separation + alignment + cohesion + avoid_walls)
-                            * delta_time
-                            * config.boid_amount;
-                    }
-                }
+        // seed a clump by making the ball under the mouse sticky
+        if is_key_pressed(KeyCode::K) {
+            let mouse = vec2(mouse_x, mouse_y);
+            if let Some(circ) = sim
+                .circles
+                .iter_mut()
+                .find(|circ| circ.position.distance(mouse) < circ.radius)
+            {
+                circ.sticky = true;
+            }
+        }
 
-                // if the magnitude of the velocity > max_velocity, clamp it back to that
-                if !config.starfield && new_velocity.length() > config.max_velocity {
-                    new_velocity = new_velocity.normalize() * config.max_velocity;
-                }
+        if is_key_pressed(KeyCode::M) {
+            sim.randomize_particle_life_matrix();
+            toast_messages.insert(
+                toast_hash!(),
+                (60, "Randomised attraction matrix".to_string()),
+            );
+        }
 
-                new_x = new_pos.x;
-                new_y = new_pos.y;
-
-                // collision detection
-                // todo: figure out good values for the search field
-                if !config.starfield {
-                    let query_range = Rect::new(x - 10.0, y - 10.0, 20.0, 20.0);
-                    let results = circles_quadtree.query(query_range);
-                    for other in results {
-                        if other == *circ {
-                            continue;
-                        }
-                        // sqrt (pow(abs(other_x - x), 2) + pow(abs(other_y - y), 2))
-                        let Circle(other_x, other_y, other_size, _, _) = *other;
-                        let dist = vec2(other_x, other_y).distance(vec2(x, y));
-                        if dist < (circle_size + other_size) {
-                            let x_dist = other_x - x;
-                            let y_dist = other_y - y;
-                            new_velocity -= vec2(x_dist, y_dist).normalize()
-                                * dist
-                                * delta_time
-                                * config.particle_repel_force;
-                        }
-                    }
-                }
+        if is_key_pressed(KeyCode::R) {
+            sim.reset();
+            toast_messages.insert(toast_hash!(), (60, "Reset".to_string()));
+        }
 
-                // disable the mouse interaction while the gui is on screen
-                if !show_gui && !config.starfield {
-                    let mut mouse_gravity = 0.0;
-                    let mut mouse_distance = config.mouse_attract_distance;
-                    if is_mouse_button_down(MouseButton::Left) {
-                        mouse_gravity = -config.mouse_attract_force;
-                        mouse_distance *= 3.0;
-                    } else if is_mouse_button_down(MouseButton::Right) {
-                        mouse_gravity = config.mouse_repel_force;
-                    }
-                    let mouse_x_dist = x - mouse_x;
-                    let mouse_y_dist = y - mouse_y;
-                    let mouse_dist = ((mouse_x_dist.powi(2) + mouse_y_dist.powi(2)) as f32).sqrt();
-                    if mouse_dist < mouse_distance {
-                        new_velocity +=
-                            Vec2::new(mouse_x_dist as f32, mouse_y_dist as f32) * mouse_gravity;
-                    }
-                }
+        clear_background(Color::from_rgba(0x00, 0x00, 0x00, 0xC0));
 
-                new_velocity.x += jiggle_x;
-                if new_x >= width - circle_size || new_x <= circle_size {
-                    if config.audio_enabled {
+        let input = InputState {
+            mouse_position: vec2(mouse_x, mouse_y),
+            mouse_delta: vec2(mouse_x, mouse_y) - mouse_last_frame,
+            left_mouse_down: is_mouse_button_down(MouseButton::Left),
+            right_mouse_down: is_mouse_button_down(MouseButton::Right),
+            // disable the mouse interaction while the gui is on screen
+            mouse_forces_enabled: !show_gui && draw_tool == DrawTool::Off,
+            tilt: is_key_down(KeyCode::Right) as i32 as f32
+                - is_key_down(KeyCode::Left) as i32 as f32,
+        };
+        for event in sim.advance(frame_time, &input) {
+            match event {
+                SimulationEvent::WallHit { circle_size } => {
+                    if sim.config.audio_enabled {
                         let beep_tone = 100.0 / (circle_size);
                         beep(beep_tone, 0.05);
                     }
-                    new_velocity.x = -(new_velocity.x / 2.0);
                 }
+            }
+        }
 
-                new_velocity.y += jiggle_y;
-                if new_y >= height - circle_size || new_y <= circle_size {
-                    if config.audio_enabled {
-                        let beep_tone = 100.0 / (circle_size);
-                        beep(beep_tone, 0.05);
-                    }
-                    new_velocity.y = -(new_velocity.y / 2.0);
+        // tint the strips along the hot and cold walls
+        if sim.config.thermal {
+            let depth = sim.config.thermal_wall_depth;
+            for (wall, color) in [
+                (
+                    sim.config.hot_wall,
+                    Color::from_rgba(0xFF, 0x40, 0x20, 0x30),
+                ),
+                (
+                    sim.config.cold_wall,
+                    Color::from_rgba(0x20, 0x60, 0xFF, 0x30),
+                ),
+            ] {
+                if let Some(region) = wall.region(depth, width, height) {
+                    draw_rectangle(region.x, region.y, region.w, region.h, color);
                 }
+            }
+        }
 
-                // make the walls actively "push" circles away
-                if new_y >= height - circle_size {
-                    if config.starfield {
-                        Vec2{x: new_x, y: new_y} = starfield_origin;
-                        new_x += jiggle_x;
-                        new_y += jiggle_y;
-                    } else {
-                        new_velocity.y -= 1.0;
-                    }
-                }
-                if new_x >= width - circle_size {
-                    if config.starfield {
-                        Vec2{x: new_x, y: new_y} = starfield_origin;
-                        new_x += jiggle_x;
-                        new_y += jiggle_y;
-                    } else {
-                        new_velocity.x -= 1.0;
-                    }
-                }
-                if new_y <= circle_size {
-                    if config.starfield {
-                        Vec2{x: new_x, y: new_y} = starfield_origin;
-                        new_x += jiggle_x;
-                        new_y += jiggle_y;
-                    } else {
-                        new_velocity.y += 1.0;
-                    }
+        for (index, circ) in sim.circles.iter().enumerate() {
+            let Circle {
+                velocity, charge, ..
+            } = *circ;
+            let (circle_size, color) = sim.appearance(circ);
+            let Vec2 { x, y } = sim.interpolated_position(index);
+            if sim.config.monochrome {
+                draw_circle(
+                    x,
+                    y,
+                    circle_size,
+                    Color {
+                        a: color.a,
+                        ..WHITE
+                    },
+                );
+            } else {
+                draw_circle(x, y, circle_size, color);
+            }
+
+            if sim.config.draw_velocities {
+                let Vec2 {
+                    x: next_x,
+                    y: next_y,
+                } = vec2(x, y) + velocity;
+                let mut color = RED;
+                if sim.config.monochrome {
+                    color = WHITE;
                 }
-                if new_x <= circle_size {
-                    if config.starfield {
-                        Vec2{x: new_x, y: new_y} = starfield_origin;
-                        new_x += jiggle_x;
-                        new_y += jiggle_y;
-                    } else {
-                        new_velocity.x += 1.0;
-                    }
+                draw_line(x, y, next_x, next_y, 1.0, color);
+            }
+
+            // mark charged balls with a plus or a minus
+            if sim.config.charges && charge != 0.0 {
+                let mark_size = circle_size / 2.0;
+                draw_line(x - mark_size, y, x + mark_size, y, 2.0, BLACK);
+                if charge > 0.0 {
+                    draw_line(x, y - mark_size, x, y + mark_size, 2.0, BLACK);
                 }
+            }
+        }
 
-                new_x = new_x.clamp(circle_size, width - circle_size);
-                new_y = new_y.clamp(circle_size, height - circle_size);
-                let new_circ = Box::new(Circle(new_x, new_y, circle_size, color, new_velocity));
-                circles_quadtree.replace(circ.clone(), new_circ.clone());
-                new_circ
-            })
-            .collect();
+        for obstacle in &sim.obstacles {
+            draw_obstacle(obstacle, GRAY);
+        }
+        for sink in &sim.sinks {
+            SceneItem::Sink(*sink).draw(GRAY);
+        }
+        for portal in &sim.portals {
+            SceneItem::Portal(*portal).draw(VIOLET);
+        }
+        for emitter in &sim.emitters {
+            SceneItem::Emitter(*emitter).draw(ORANGE);
+        }
+        if let Some(preview) =
+            drag_start.and_then(|start| draw_tool.item(start, vec2(mouse_x, mouse_y), drag_angle))
+        {
+            preview.draw(LIGHTGRAY);
+        }
 
-        circles.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
+        if sim.config.draw_springs {
+            let color = if sim.config.monochrome { WHITE } else { YELLOW };
+            for (start, end) in sim.spring_lines() {
+                draw_line(start.x, start.y, end.x, end.y, 1.0, color);
+            }
+        }
 
         draw_circle(mouse_x, mouse_y, 5.0, BLUE);
 
@@ -730,31 +576,38 @@ async fn main() {
             draw_rectangle(
                 0.0,
                 0.0,
-                200.0,
-                80.0,
+                450.0,
+                144.0,
                 Color::from_rgba(0x00, 0xFF, 0xFF, 0xA0),
             );
             let mut s = String::new();
-            write!(s, "Jiggle: {}", config.jiggle).unwrap();
+            write!(s, "Jiggle: {}", sim.config.jiggle).unwrap();
             draw_text_ex(s.as_str(), 0.0, 32.0, hud_textparams.clone());
             s.clear();
             let fps = get_fps();
             s.clear();
             write!(s, "FPS: {fps}").unwrap();
             draw_text_ex(s.as_str(), 0.0, 64.0, hud_textparams.clone());
-            let quadtree_bounds = circles_quadtree.get_bounds();
+            s.clear();
+            write!(s, "Seed: {}", sim.seed()).unwrap();
+            draw_text_ex(s.as_str(), 0.0, 96.0, hud_textparams.clone());
+            s.clear();
+            write!(s, "Mean temp: {:.2}", sim.mean_temperature()).unwrap();
+            draw_text_ex(s.as_str(), 0.0, 128.0, hud_textparams.clone());
+            let index_bounds = sim.spatial_index.bounds();
             draw_rectangle_lines(
-                quadtree_bounds.x,
-                quadtree_bounds.y,
-                quadtree_bounds.w,
-                quadtree_bounds.h,
+                index_bounds.x,
+                index_bounds.y,
+                index_bounds.w,
+                index_bounds.h,
                 1.0,
                 RED,
             );
         }
         // render toast messages (temporary messages that fade out after a few frames)
-        let mut toast_index = 0;
-        for (hash, (frame_count, msg)) in toast_messages.clone() {
+        for (toast_index, (hash, (frame_count, msg))) in
+            toast_messages.clone().into_iter().enumerate()
+        {
             let TextDimensions {
                 width: toast_text_width,
                 height: toast_text_height,
@@ -789,114 +642,460 @@ async fn main() {
             } else {
                 toast_messages.insert(hash, (frame_count - 1, msg));
             }
-            toast_index += 1;
         }
 
         if show_gui {
-            let mut window_height = 400.0;
-            if config.boids {
+            let mut window_height = 520.0;
+            if sim.config.jiggle_model == JiggleModel::Brownian {
+                window_height += 20.0;
+            }
+            if sim.config.boids {
                 window_height += 150.0;
             }
+            if sim.config.gravity_enabled {
+                window_height += 60.0;
+            }
+            if sim.config.nbody {
+                window_height += 40.0;
+            }
+            if sim.config.charges {
+                window_height += 60.0;
+            }
+            if sim.config.lifetimes {
+                window_height += 120.0;
+            }
+            if sim.config.fluid {
+                window_height += 80.0;
+            }
+            if sim.config.merging {
+                window_height += 20.0;
+            }
+            if sim.config.stickiness > 0.0 {
+                window_height += 40.0;
+            }
+            if sim.config.thermal {
+                window_height += 120.0;
+            }
+            if !sim.config.starfield {
+                window_height += 100.0;
+            }
+            if sim.config.fragmenting {
+                window_height += 40.0;
+            }
+            if sim.config.collision_model == CollisionModel::Elastic {
+                window_height += 20.0;
+            }
             Window::new(hash!(), vec2(width - 620., 20.), vec2(420., window_height))
                 .label("Controls")
                 .close_button(false)
                 .ui(&mut root_ui(), |ui| {
-                    ui.checkbox(hash!(), "audio", &mut config.audio_enabled);
-                    ui.slider(hash!(), "Jiggle", 0.0..100.0, &mut config.jiggle);
+                    ui.checkbox(hash!(), "audio", &mut sim.config.audio_enabled);
+                    let mut jiggle_model = JiggleModel::ALL
+                        .iter()
+                        .position(|model| *model == sim.config.jiggle_model)
+                        .unwrap();
+                    ui.combo_box(
+                        hash!(),
+                        "jiggle model",
+                        &["uniform", "brownian"],
+                        &mut jiggle_model,
+                    );
+                    sim.config.jiggle_model = JiggleModel::ALL[jiggle_model];
+                    if sim.config.jiggle_model == JiggleModel::Brownian {
+                        ui.slider(hash!(), "diffusion", 0.0..2000.0, &mut sim.config.diffusion);
+                        ui.slider(
+                            hash!(),
+                            "damping",
+                            0.1..20.0,
+                            &mut sim.config.brownian_damping,
+                        );
+                    } else {
+                        ui.slider(hash!(), "Jiggle", 0.0..100.0, &mut sim.config.jiggle);
+                    }
                     ui.slider(
                         hash!(),
                         "min ball size",
                         1.0..50.0,
-                        &mut config.min_circle_size,
+                        &mut sim.config.min_circle_size,
                     );
                     ui.slider(
                         hash!(),
                         "max ball size",
                         1.0..100.0,
-                        &mut config.max_circle_size,
+                        &mut sim.config.max_circle_size,
                     );
                     ui.slider(
                         hash!(),
                         "push force",
                         1.0..15.0,
-                        &mut config.mouse_repel_force,
+                        &mut sim.config.mouse_repel_force,
                     );
                     ui.slider(
                         hash!(),
                         "pull force",
                         0.05..1.0,
-                        &mut config.mouse_attract_force,
+                        &mut sim.config.mouse_attract_force,
                     );
                     ui.slider(
                         hash!(),
                         "pull dist.",
                         1.0..500.0,
-                        &mut config.mouse_attract_distance,
+                        &mut sim.config.mouse_attract_distance,
                     );
                     ui.slider(
                         hash!(),
                         "drag coef.",
                         0.0..250.0,
-                        &mut config.medium_viscosity,
+                        &mut sim.config.medium_viscosity,
                     );
                     ui.slider(
                         hash!(),
                         "ball count",
                         1.0..15000.0,
-                        &mut config.num_circles_ui,
+                        &mut sim.config.num_circles_ui,
                     );
                     ui.slider(
                         hash!(),
                         "ball repel",
                         1.0..100.0,
-                        &mut config.particle_repel_force,
+                        &mut sim.config.particle_repel_force,
                     );
-                    ui.checkbox(hash!(), "gravity", &mut config.gravity_enabled);
+                    let mut elastic = sim.config.collision_model == CollisionModel::Elastic;
+                    ui.checkbox(hash!(), "elastic coll.", &mut elastic);
+                    sim.config.collision_model = if elastic {
+                        CollisionModel::Elastic
+                    } else {
+                        CollisionModel::SoftRepel
+                    };
+                    if elastic {
+                        ui.slider(
+                            hash!(),
+                            "restitution",
+                            0.0..1.0,
+                            &mut sim.config.restitution,
+                        );
+                    }
+                    ui.checkbox(hash!(), "gravity", &mut sim.config.gravity_enabled);
+                    if sim.config.gravity_enabled {
+                        ui.slider(
+                            hash!(),
+                            "gravity str.",
+                            0.0..2000.0,
+                            &mut sim.config.gravity_strength,
+                        );
+                        ui.slider(
+                            hash!(),
+                            "gravity angle",
+                            0.0..360.0,
+                            &mut sim.config.gravity_angle,
+                        );
+                        let mut tilt = GravityTilt::ALL
+                            .iter()
+                            .position(|tilt| *tilt == sim.config.gravity_tilt)
+                            .unwrap();
+                        ui.combo_box(hash!(), "tilt", &["off", "arrow keys", "mouse"], &mut tilt);
+                        sim.config.gravity_tilt = GravityTilt::ALL[tilt];
+                    }
+                    ui.checkbox(hash!(), "n-body", &mut sim.config.nbody);
+                    if sim.config.nbody {
+                        ui.slider(
+                            hash!(),
+                            "attraction",
+                            0.0..10000.0,
+                            &mut sim.config.nbody_strength,
+                        );
+                        ui.slider(hash!(), "theta", 0.0..1.5, &mut sim.config.nbody_theta);
+                    }
+                    ui.checkbox(hash!(), "charges", &mut sim.config.charges);
+                    if sim.config.charges {
+                        ui.slider(
+                            hash!(),
+                            "charge str.",
+                            0.0..5000.0,
+                            &mut sim.config.charge_strength,
+                        );
+                        ui.slider(
+                            hash!(),
+                            "charge range",
+                            10.0..500.0,
+                            &mut sim.config.charge_range,
+                        );
+                        ui.slider(
+                            hash!(),
+                            "charged frac.",
+                            0.0..1.0,
+                            &mut sim.config.charged_fraction,
+                        );
+                    }
+                    ui.checkbox(hash!(), "merging", &mut sim.config.merging);
+                    if sim.config.merging {
+                        ui.slider(
+                            hash!(),
+                            "merge speed",
+                            0.0..500.0,
+                            &mut sim.config.merge_speed,
+                        );
+                    }
+                    ui.checkbox(hash!(), "fragmenting", &mut sim.config.fragmenting);
+                    if sim.config.fragmenting {
+                        ui.slider(
+                            hash!(),
+                            "break speed",
+                            0.0..500.0,
+                            &mut sim.config.fragment_speed,
+                        );
+                        let mut fragment_count = sim.config.fragment_count as f32;
+                        ui.slider(hash!(), "pieces", 2.0..8.0, &mut fragment_count);
+                        sim.config.fragment_count = fragment_count.round() as u32;
+                    }
+                    ui.checkbox(hash!(), "fluid", &mut sim.config.fluid);
+                    if sim.config.fluid {
+                        ui.slider(
+                            hash!(),
+                            "smoothing",
+                            10.0..100.0,
+                            &mut sim.config.fluid_smoothing_radius,
+                        );
+                        ui.slider(
+                            hash!(),
+                            "rest density",
+                            0.001..0.02,
+                            &mut sim.config.fluid_rest_density,
+                        );
+                        ui.slider(
+                            hash!(),
+                            "stiffness",
+                            0.0..5000000.0,
+                            &mut sim.config.fluid_stiffness,
+                        );
+                        ui.slider(
+                            hash!(),
+                            "fluid visc.",
+                            0.0..100.0,
+                            &mut sim.config.fluid_viscosity,
+                        );
+                    }
+                    ui.checkbox(hash!(), "thermal", &mut sim.config.thermal);
+                    if sim.config.thermal {
+                        ui.slider(
+                            hash!(),
+                            "conductivity",
+                            0.0..10.0,
+                            &mut sim.config.heat_conductivity,
+                        );
+                        ui.slider(
+                            hash!(),
+                            "hot temp.",
+                            0.0..20.0,
+                            &mut sim.config.hot_temperature,
+                        );
+                        ui.slider(
+                            hash!(),
+                            "cold temp.",
+                            0.0..20.0,
+                            &mut sim.config.cold_temperature,
+                        );
+                        let walls = ["none", "left", "right", "top", "bottom"];
+                        let mut hot_wall = Wall::ALL
+                            .iter()
+                            .position(|wall| *wall == sim.config.hot_wall)
+                            .unwrap();
+                        ui.combo_box(hash!(), "hot wall", &walls, &mut hot_wall);
+                        sim.config.hot_wall = Wall::ALL[hot_wall];
+                        let mut cold_wall = Wall::ALL
+                            .iter()
+                            .position(|wall| *wall == sim.config.cold_wall)
+                            .unwrap();
+                        ui.combo_box(hash!(), "cold wall", &walls, &mut cold_wall);
+                        sim.config.cold_wall = Wall::ALL[cold_wall];
+                        ui.checkbox(
+                            hash!(),
+                            "colour by temp.",
+                            &mut sim.config.color_by_temperature,
+                        );
+                    }
+                    ui.checkbox(hash!(), "particle life", &mut sim.config.particle_life);
+                    ui.checkbox(hash!(), "lifetimes", &mut sim.config.lifetimes);
+                    if sim.config.lifetimes {
+                        ui.slider(
+                            hash!(),
+                            "min lifetime",
+                            0.1..60.0,
+                            &mut sim.config.min_lifetime,
+                        );
+                        ui.slider(
+                            hash!(),
+                            "max lifetime",
+                            0.1..60.0,
+                            &mut sim.config.max_lifetime,
+                        );
+                        ui.slider(
+                            hash!(),
+                            "hue shift",
+                            0.0..1.0,
+                            &mut sim.config.age_hue_shift,
+                        );
+                        ui.checkbox(hash!(), "fade", &mut sim.config.age_fade);
+                        ui.checkbox(hash!(), "shrink", &mut sim.config.age_shrink);
+                        ui.checkbox(hash!(), "respawn", &mut sim.config.respawn_expired);
+                    }
                     ui.checkbox(
                         hash!(),
                         "allow phasing",
-                        &mut config.allow_ball_intersection,
+                        &mut sim.config.allow_ball_intersection,
                     );
-                    ui.checkbox(hash!(), "draw vel.", &mut config.draw_velocities);
-                    ui.slider(hash!(), "speed lim.", 10.0..500.0, &mut config.max_velocity);
-                    if !config.starfield {
-                        ui.checkbox(hash!(), "boids", &mut config.boids);
+                    ui.checkbox(hash!(), "draw vel.", &mut sim.config.draw_velocities);
+                    ui.checkbox(hash!(), "draw springs", &mut sim.config.draw_springs);
+                    ui.slider(hash!(), "stickiness", 0.0..1.0, &mut sim.config.stickiness);
+                    if sim.config.stickiness > 0.0 {
+                        ui.checkbox(hash!(), "all sticky", &mut sim.config.all_sticky);
+                        ui.slider(
+                            hash!(),
+                            "bond stretch",
+                            0.0..2.0,
+                            &mut sim.config.bond_break_stretch,
+                        );
                     }
-                    if config.boids {
-                        ui.slider(hash!(), "box size", 1.0..50.0, &mut config.boids_box_size);
+                    if !sim.config.starfield {
+                        let modes = ["reflect", "wrap", "absorb", "respawn", "open"];
+                        let config = &mut sim.config;
+                        for (label, boundary) in [
+                            ("left edge", &mut config.boundary_left),
+                            ("right edge", &mut config.boundary_right),
+                            ("top edge", &mut config.boundary_top),
+                            ("bottom edge", &mut config.boundary_bottom),
+                        ] {
+                            let mut mode = BoundaryMode::ALL
+                                .iter()
+                                .position(|mode| mode == boundary)
+                                .unwrap();
+                            ui.combo_box(hash!(label), label, &modes, &mut mode);
+                            *boundary = BoundaryMode::ALL[mode];
+                        }
+                        ui.slider(
+                            hash!(),
+                            "wall bounce",
+                            0.0..1.0,
+                            &mut config.wall_restitution,
+                        );
+                    }
+                    ui.slider(
+                        hash!(),
+                        "speed lim.",
+                        10.0..500.0,
+                        &mut sim.config.max_velocity,
+                    );
+                    ui.slider(
+                        hash!(),
+                        "physics hz",
+                        30.0..240.0,
+                        &mut sim.config.physics_hz,
+                    );
+                    let mut integrator = Integrator::ALL
+                        .iter()
+                        .position(|integrator| *integrator == sim.config.integrator)
+                        .unwrap();
+                    ui.combo_box(
+                        hash!(),
+                        "integrator",
+                        &["explicit Euler", "semi-implicit Euler", "position Verlet"],
+                        &mut integrator,
+                    );
+                    sim.config.integrator = Integrator::ALL[integrator];
+                    if !sim.config.starfield {
+                        ui.checkbox(hash!(), "boids", &mut sim.config.boids);
+                    }
+                    if sim.config.boids {
+                        ui.slider(
+                            hash!(),
+                            "perception",
+                            1.0..100.0,
+                            &mut sim.config.boids_perception_radius,
+                        );
                         ui.slider(
                             hash!(),
                             "sep. amt",
                             10.0..100.0,
-                            &mut config.separation_distance,
+                            &mut sim.config.separation_distance,
+                        );
+                        ui.slider(
+                            hash!(),
+                            "sep. wt.",
+                            0.1..5.0,
+                            &mut sim.config.separation_weight,
                         );
-                        ui.slider(hash!(), "sep. wt.", 0.1..5.0, &mut config.separation_weight);
                         ui.slider(
                             hash!(),
                             "align. wt.",
                             0.1..5.0,
-                            &mut config.alignment_weight,
+                            &mut sim.config.alignment_weight,
+                        );
+                        ui.slider(
+                            hash!(),
+                            "coh. wt.",
+                            0.1..5.0,
+                            &mut sim.config.cohesion_weight,
                         );
-                        ui.slider(hash!(), "coh. wt.", 0.1..5.0, &mut config.cohesion_weight);
-                        ui.slider(hash!(), "boid wt.", 1.0..50.0, &mut config.boid_amount);
+                        ui.slider(hash!(), "boid wt.", 1.0..50.0, &mut sim.config.boid_amount);
                         ui.slider(
                             hash!(),
                             "avoid wl",
                             0.01..0.5,
-                            &mut config.avoid_walls_weight,
+                            &mut sim.config.avoid_walls_weight,
                         );
                         ui.slider(
                             hash!(),
                             "avoid wl dist.",
                             10.0..500.0,
-                            &mut config.avoid_walls_distance,
-                        );  
+                            &mut sim.config.avoid_walls_distance,
+                        );
                     } else {
-                        ui.checkbox(hash!(), "starfield", &mut config.starfield);
-                        if config.starfield {
-                            ui.slider(hash!(), "warp factor", 1.0..10.0, &mut config.warp_factor);
-                            ui.checkbox(hash!(), "monochrome", &mut config.monochrome);
-                        } 
+                        ui.checkbox(hash!(), "starfield", &mut sim.config.starfield);
+                        if sim.config.starfield {
+                            ui.slider(
+                                hash!(),
+                                "warp factor",
+                                1.0..10.0,
+                                &mut sim.config.warp_factor,
+                            );
+                            ui.checkbox(hash!(), "monochrome", &mut sim.config.monochrome);
+                        }
+                    }
+                });
+        }
+
+        if show_gui && sim.config.particle_life {
+            let num_species = sim.config.particle_life_matrix.len();
+            let window_height = 120.0 + (num_species * num_species) as f32 * 22.0;
+            Window::new(hash!(), vec2(width - 960., 20.), vec2(320., window_height))
+                .label("Particle life")
+                .close_button(false)
+                .ui(&mut root_ui(), |ui| {
+                    let mut species = sim.config.particle_life_species as f32;
+                    ui.slider(hash!(), "species", 1.0..MAX_SPECIES as f32, &mut species);
+                    sim.config.particle_life_species = species.round() as u32;
+                    ui.slider(
+                        hash!(),
+                        "radius",
+                        10.0..300.0,
+                        &mut sim.config.particle_life_radius,
+                    );
+                    ui.slider(
+                        hash!(),
+                        "strength",
+                        0.0..20.0,
+                        &mut sim.config.particle_life_strength,
+                    );
+                    ui.label(None, "attraction of row to column (m: randomise)");
+                    for (i, row) in sim.config.particle_life_matrix.iter_mut().enumerate() {
+                        for (j, attraction) in row.iter_mut().enumerate() {
+                            ui.slider(
+                                hash!("attraction", i, j),
+                                &format!("{} -> {}", i + 1, j + 1),
+                                -1.0..1.0,
+                                attraction,
+                            );
+                        }
                     }
                 });
         }
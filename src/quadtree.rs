@@ -1,143 +1,536 @@
-use crate::Circle;
 use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+/// What the quadtree stores for each ball: its index into the simulation's ball storage.
+pub type Handle = usize;
+
+/// marks the end of a list / a missing node
+const NONE: u32 = u32::MAX;
+const ROOT: u32 = 0;
+
+/// What the quadtree does with an item whose position is outside its bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutOfBounds {
+    /// keep doubling the size of the root towards the item until it fits
+    #[default]
+    Grow,
+    /// store the item at the nearest point inside the bounds, which is also where queries and
+    /// `position` will report it
+    Clamp,
+}
+
+/// The tunable parts of a [`Quadtree`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuadtreeSettings {
+    /// how many items a leaf holds before it gets subdivided
+    pub node_capacity: u32,
+    /// leaves this deep are never subdivided, however many items end up in them (e.g. lots of
+    /// balls piled up on the same point)
+    pub max_depth: u32,
+    pub out_of_bounds: OutOfBounds,
+}
+
+impl Default for QuadtreeSettings {
+    fn default() -> Self {
+        QuadtreeSettings {
+            node_capacity: 5,
+            max_depth: 12,
+            out_of_bounds: OutOfBounds::Grow,
+        }
+    }
+}
+
+/// A node covers the points from `min` up to but not including `max`, except for the root which
+/// also includes its right and bottom edges.  The edges are stored as exact values (rather than
+/// a `Rect`) so a child's edges are bit-for-bit its parent's edges and centre, which means every
+/// point belongs to exactly one leaf and rounding can't make a query skip a node it should visit.
+#[derive(Debug, Clone)]
 struct QuadtreeNode {
-    bounds: Rect,
-    circles: Vec<Box<Circle>>,
-    children: Option<Box<[Option<Box<QuadtreeNode>>; 4]>>,
+    min: Vec2,
+    max: Vec2,
+    parent: u32,
+    /// the four children are stored next to each other (nw, ne, sw, se) starting at this index,
+    /// or NONE if this is a leaf
+    first_child: u32,
+    /// head of the linked list of items in this leaf
+    first_item: u32,
+    len: u32,
+    depth: u32,
+    /// total mass of everything under this node, as of the last call to
+    /// [`Quadtree::compute_mass_distribution`]
+    mass: f32,
+    center_of_mass: Vec2,
 }
 
 impl QuadtreeNode {
-    fn new(bounds: Rect) -> Self {
+    fn new(min: Vec2, max: Vec2, parent: u32, depth: u32) -> Self {
         QuadtreeNode {
-            bounds,
-            circles: Vec::new(),
-            children: None,
+            min,
+            max,
+            parent,
+            first_child: NONE,
+            first_item: NONE,
+            len: 0,
+            depth,
+            mass: 0.0,
+            center_of_mass: Vec2::ZERO,
         }
     }
 
+    fn is_leaf(&self) -> bool {
+        self.first_child == NONE
+    }
 
-    fn subdivide(&mut self) {
-        let (x, y, w, h) = (
-            self.bounds.x,
-            self.bounds.y,
-            self.bounds.w / 2.0,
-            self.bounds.h / 2.0,
-        );
+    fn center(&self) -> Vec2 {
+        (self.min + self.max) * 0.5
+    }
 
-        let nw = Rect::new(x, y, w, h);
-        let ne = Rect::new(x + w, y, w, h);
-        let sw = Rect::new(x, y + h, w, h);
-        let se = Rect::new(x + w, y + h, w, h);
+    /// Which of the four children `position` falls in.  Points on a centre line belong to the
+    /// child east/south of it.
+    fn child_for(&self, position: Vec2) -> u32 {
+        let center = self.center();
+        let east = (position.x >= center.x) as u32;
+        let south = (position.y >= center.y) as u32;
+        self.first_child + east + 2 * south
+    }
 
-        self.children = Some(Box::new([
-            Some(Box::new(QuadtreeNode::new(nw))),
-            Some(Box::new(QuadtreeNode::new(ne))),
-            Some(Box::new(QuadtreeNode::new(sw))),
-            Some(Box::new(QuadtreeNode::new(se))),
-        ]));
+    /// Whether `position` is inside this node, not counting the right and bottom edges.
+    fn contains(&self, position: Vec2) -> bool {
+        position.cmpge(self.min).all() && position.cmplt(self.max).all()
+    }
+
+    fn overlaps(&self, range: &Rect) -> bool {
+        self.min.x <= range.right()
+            && self.max.x >= range.left()
+            && self.min.y <= range.bottom()
+            && self.max.y >= range.top()
+    }
+}
+
+/// One slot per handle; the slots double as the links of each leaf's list of items.
+#[derive(Debug, Clone, Copy)]
+struct QuadtreeItem {
+    position: Vec2,
+    /// the leaf this item is in, or NONE if the handle isn't in the tree
+    node: u32,
+    /// the next item in the same leaf
+    next: u32,
+    /// as of the last call to [`Quadtree::compute_mass_distribution`]
+    mass: f32,
+}
+
+impl QuadtreeItem {
+    const ABSENT: QuadtreeItem = QuadtreeItem {
+        position: Vec2::ZERO,
+        node: NONE,
+        next: NONE,
+        mass: 0.0,
+    };
+}
+
+/// A quadtree of handles to balls, keyed by position.  Nodes and items live in flat arrays that
+/// are reused between rebuilds, so rebuilding the whole tree every step doesn't allocate once
+/// it has warmed up, and queries return iterators that never allocate either.
+///
+/// Every item lives in exactly one leaf, including items on the edges between nodes.  Items
+/// outside the bounds are handled according to [`QuadtreeSettings::out_of_bounds`], and items
+/// whose position isn't finite are ignored.
+#[derive(Debug, Clone)]
+pub struct Quadtree {
+    settings: QuadtreeSettings,
+    nodes: Vec<QuadtreeNode>,
+    items: Vec<QuadtreeItem>,
+    len: usize,
+}
+
+impl Quadtree {
+    pub fn new(bounds: Rect) -> Self {
+        Quadtree::with_settings(bounds, QuadtreeSettings::default())
     }
 
-    fn insert(&mut self, circle: Box<Circle>) {
-        if !self.bounds.contains(vec2(circle.0, circle.1).into()) {
+    pub fn with_settings(bounds: Rect, settings: QuadtreeSettings) -> Self {
+        let mut quadtree = Quadtree {
+            settings,
+            nodes: Vec::new(),
+            items: Vec::new(),
+            len: 0,
+        };
+        quadtree.clear(bounds);
+        quadtree
+    }
+
+    pub fn settings(&self) -> QuadtreeSettings {
+        self.settings
+    }
+
+    /// The area covered by the root, which may have grown past the bounds the tree was given.
+    pub fn get_bounds(&self) -> Rect {
+        let root = &self.nodes[ROOT as usize];
+        let size = root.max - root.min;
+        Rect::new(root.min.x, root.min.y, size.x, size.y)
+    }
+
+    /// Remove everything from the tree and change its bounds.
+    pub fn clear(&mut self, bounds: Rect) {
+        let min = bounds.point();
+        self.nodes.clear();
+        self.nodes
+            .push(QuadtreeNode::new(min, min + bounds.size(), NONE, 0));
+        self.items.clear();
+        self.len = 0;
+    }
+
+    /// Clear the tree and fill it with `items` in one go.
+    pub fn rebuild(&mut self, bounds: Rect, items: impl IntoIterator<Item = (Handle, Vec2)>) {
+        self.clear(bounds);
+        for (handle, position) in items {
+            self.insert(handle, position);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.items.get(handle).is_some_and(|item| item.node != NONE)
+    }
+
+    pub fn position(&self, handle: Handle) -> Option<Vec2> {
+        self.items
+            .get(handle)
+            .filter(|item| item.node != NONE)
+            .map(|item| item.position)
+    }
+
+    pub fn insert(&mut self, handle: Handle, position: Vec2) {
+        if !position.is_finite() {
             return;
         }
+        if self.contains(handle) {
+            self.remove(handle);
+        }
+        let position = self.fit_to_bounds(position);
+        if handle >= self.items.len() {
+            self.items.resize(handle + 1, QuadtreeItem::ABSENT);
+        }
+        self.items[handle].position = position;
 
-        if let Some(children) = &mut self.children {
-            for child in children.iter_mut() {
-                child.as_mut().unwrap().insert(circle.clone());
-            }
+        let mut node = ROOT;
+        while !self.nodes[node as usize].is_leaf() {
+            node = self.nodes[node as usize].child_for(position);
+        }
+        self.push_item(node, handle as u32);
+        self.len += 1;
+        self.subdivide_if_full(node);
+    }
+
+    pub fn remove(&mut self, handle: Handle) {
+        if !self.contains(handle) {
+            return;
+        }
+        let handle = handle as u32;
+        let node = self.items[handle as usize].node;
+        let next = self.items[handle as usize].next;
+        let leaf = &mut self.nodes[node as usize];
+        leaf.len -= 1;
+        if leaf.first_item == handle {
+            leaf.first_item = next;
         } else {
-            self.circles.push(circle);
+            let mut item = leaf.first_item;
+            while self.items[item as usize].next != handle {
+                item = self.items[item as usize].next;
+            }
+            self.items[item as usize].next = next;
+        }
+        self.items[handle as usize] = QuadtreeItem::ABSENT;
+        self.len -= 1;
+    }
 
-            if self.circles.len() > 5 && self.children.is_none() {
-                self.subdivide();
-                while let Some(c) = self.circles.pop() {
-                    self.insert(c);
-                }
+    /// Move an item to a new position, which is cheap if it stays inside the same leaf.
+    pub fn update(&mut self, handle: Handle, position: Vec2) {
+        if self.contains(handle) {
+            let item = &mut self.items[handle];
+            if self.nodes[item.node as usize].contains(position) {
+                item.position = position;
+                return;
             }
         }
+        self.insert(handle, position);
     }
 
-    fn remove(&mut self, circ: Box<Circle>) {
-        if !self.bounds.contains(vec2(circ.0, circ.1).into()) {
-            return;
+    /// Every handle whose position lies inside `range`.
+    pub fn query(&self, range: Rect) -> QuadtreeQuery<'_> {
+        let root = &self.nodes[ROOT as usize];
+        let overlaps = root.overlaps(&range);
+        QuadtreeQuery {
+            tree: self,
+            range,
+            node: if overlaps { ROOT } else { NONE },
+            item: if overlaps { root.first_item } else { NONE },
         }
+    }
 
-        // If the node has children, recursively remove in the children
-        if let Some(children) = &mut self.children {
-            for child in children.iter_mut() {
-                child.as_mut().unwrap().remove(circ.clone());
+    /// Work out the total mass and centre of mass of every node, for
+    /// [`Quadtree::gravity_at`].  This has to be called again after the tree changes.
+    pub fn compute_mass_distribution(&mut self, mass_of: impl Fn(Handle) -> f32) {
+        // children always come after their parent, so going backwards sees every child before
+        // its parent
+        for node in (0..self.nodes.len()).rev() {
+            let mut mass = 0.0;
+            let mut weighted_position = Vec2::ZERO;
+            if self.nodes[node].is_leaf() {
+                let mut handle = self.nodes[node].first_item;
+                while handle != NONE {
+                    let item = &mut self.items[handle as usize];
+                    item.mass = mass_of(handle as Handle);
+                    mass += item.mass;
+                    weighted_position += item.position * item.mass;
+                    handle = item.next;
+                }
+            } else {
+                let first_child = self.nodes[node].first_child as usize;
+                for child in &self.nodes[first_child..first_child + 4] {
+                    mass += child.mass;
+                    weighted_position += child.center_of_mass * child.mass;
+                }
             }
-        } else {
-            // Remove in the node's circles
-            if let Some(position) = self.circles.iter().position(|c| *c == circ) {
-                self.circles.remove(position);
+            let node = &mut self.nodes[node];
+            node.mass = mass;
+            node.center_of_mass = if mass > 0.0 {
+                weighted_position / mass
+            } else {
+                node.center()
+            };
+        }
+    }
+
+    /// The gravitational pull at `position` from everything in the tree, as
+    /// `sum(mass * offset / (distance^2 + softening^2)^1.5)`, using the Barnes-Hut
+    /// approximation: a node that looks smaller than `theta` from `position` (its width divided
+    /// by its distance) is treated as a single mass at its centre of mass.  A `theta` of 0 gives
+    /// the exact sum.  Relies on [`Quadtree::compute_mass_distribution`] being up to date.
+    ///
+    /// An item at exactly `position` contributes nothing, so a ball can ask for the pull on
+    /// itself without excluding itself.
+    pub fn gravity_at(&self, position: Vec2, theta: f32, softening: f32) -> Vec2 {
+        let softening_squared = softening * softening;
+        let pull = |center: Vec2, mass: f32| {
+            let offset = center - position;
+            let distance_squared = offset.length_squared() + softening_squared;
+            if distance_squared == 0.0 {
+                return Vec2::ZERO;
             }
+            offset * mass / (distance_squared * distance_squared.sqrt())
+        };
 
-            // If the node is subdivided, remove in the children as well
-            if let Some(children) = &mut self.children {
-                for child in children.iter_mut() {
-                    child.as_mut().unwrap().remove(circ.clone());
+        let mut total = Vec2::ZERO;
+        let mut node = ROOT;
+        while node != NONE {
+            let current = &self.nodes[node as usize];
+            if current.is_leaf() {
+                let mut item = current.first_item;
+                while item != NONE {
+                    let QuadtreeItem {
+                        position: item_position,
+                        mass,
+                        next,
+                        ..
+                    } = self.items[item as usize];
+                    total += pull(item_position, mass);
+                    item = next;
+                }
+            } else {
+                let width = (current.max - current.min).max_element();
+                let distance = current.center_of_mass.distance(position);
+                if width < theta * distance {
+                    total += pull(current.center_of_mass, current.mass);
+                } else {
+                    node = current.first_child;
+                    continue;
                 }
             }
+            node = self.next_sibling(node);
         }
+        total
     }
 
-    fn query(&self, range: Rect, results: &mut Vec<Box<Circle>>) {
-        if !self.bounds.overlaps(&range) {
-            return;
+    /// The node after `node` in a depth-first walk that skips `node`'s children (its next
+    /// sibling, or its parent's, and so on up), or NONE once the whole tree has been visited.
+    fn next_sibling(&self, mut node: u32) -> u32 {
+        loop {
+            if node == ROOT {
+                return NONE;
+            }
+            let first_sibling = self.nodes[self.nodes[node as usize].parent as usize].first_child;
+            if node < first_sibling + 3 {
+                return node + 1;
+            }
+            node = self.nodes[node as usize].parent;
         }
+    }
 
-        for circle in &self.circles {
-            if range.contains(vec2(circle.0, circle.1)) {
-                results.push(circle.clone());
-            }
+    /// Apply the out of bounds policy to `position`, returning where the item should be stored.
+    fn fit_to_bounds(&mut self, position: Vec2) -> Vec2 {
+        let root = &self.nodes[ROOT as usize];
+        let (min, max) = (root.min, root.max);
+        if position.cmpge(min).all() && position.cmple(max).all() {
+            return position;
         }
+        // growing towards something absurdly far away would overflow, so clamp that instead
+        if self.settings.out_of_bounds == OutOfBounds::Grow && self.grow_to_fit(position) {
+            return position;
+        }
+        position.clamp(min, max)
+    }
 
-        if let Some(children) = &self.children {
-            for child in children.iter() {
-                child.as_ref().unwrap().query(range, results);
+    /// Double the size of the root towards `position` until `position` is inside it, then put
+    /// everything back in.  Returns false (leaving the tree alone) if the root would stop being
+    /// finite.
+    fn grow_to_fit(&mut self, position: Vec2) -> bool {
+        let QuadtreeNode {
+            mut min, mut max, ..
+        } = self.nodes[ROOT as usize];
+        while !(position.cmpge(min).all() && position.cmple(max).all()) {
+            // an empty root can't be doubled, so give it some size first
+            let size = (max - min).max(Vec2::ONE);
+            if position.x < min.x {
+                min.x = max.x - size.x * 2.0;
+            } else if position.x > max.x {
+                max.x = min.x + size.x * 2.0;
+            }
+            if position.y < min.y {
+                min.y = max.y - size.y * 2.0;
+            } else if position.y > max.y {
+                max.y = min.y + size.y * 2.0;
+            }
+            if !(min.is_finite() && max.is_finite()) {
+                return false;
             }
         }
+
+        let items: Vec<(Handle, Vec2)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| item.node != NONE)
+            .map(|(handle, item)| (handle, item.position))
+            .collect();
+        let size = max - min;
+        self.rebuild(Rect::new(min.x, min.y, size.x, size.y), items);
+        true
     }
-}
 
-#[derive(Debug)]
-pub struct Quadtree {
-    root: QuadtreeNode,
-    bounds: Rect,
-}
+    fn push_item(&mut self, node: u32, handle: u32) {
+        let leaf = &mut self.nodes[node as usize];
+        let item = &mut self.items[handle as usize];
+        item.node = node;
+        item.next = leaf.first_item;
+        leaf.first_item = handle;
+        leaf.len += 1;
+    }
 
-impl Quadtree {
-    pub fn new(bounds: Rect) -> Self {
-        Quadtree {
-            root: QuadtreeNode::new(bounds),
-            bounds,
+    fn subdivide_if_full(&mut self, node: u32) {
+        let leaf = &self.nodes[node as usize];
+        if leaf.len > self.settings.node_capacity && leaf.depth < self.settings.max_depth {
+            self.subdivide(node);
         }
     }
 
-    pub fn get_bounds(&self) -> Rect {
-        self.bounds
-    }
+    fn subdivide(&mut self, node: u32) {
+        let parent = &self.nodes[node as usize];
+        let (min, max, center, depth) = (parent.min, parent.max, parent.center(), parent.depth);
+        let first_child = self.nodes.len() as u32;
+        for (child_min, child_max) in [
+            (min, center),
+            (vec2(center.x, min.y), vec2(max.x, center.y)),
+            (vec2(min.x, center.y), vec2(center.x, max.y)),
+            (center, max),
+        ] {
+            self.nodes
+                .push(QuadtreeNode::new(child_min, child_max, node, depth + 1));
+        }
 
-    pub fn insert(&mut self, circle: Box<Circle>) {
-        self.root.insert(circle);
-    }
+        let parent = &mut self.nodes[node as usize];
+        parent.first_child = first_child;
+        let mut item = parent.first_item;
+        parent.first_item = NONE;
+        parent.len = 0;
+        while item != NONE {
+            let next = self.items[item as usize].next;
+            let child = self.nodes[node as usize].child_for(self.items[item as usize].position);
+            self.push_item(child, item);
+            item = next;
+        }
 
-    pub fn replace(&mut self, old: Box<Circle>, new: Box<Circle>) {
-        self.root.remove(old);
-        self.root.insert(new);
+        // if everything landed in the same child it may need splitting again
+        for child in first_child..first_child + 4 {
+            self.subdivide_if_full(child);
+        }
     }
+}
+
+/// Iterator over the handles inside a rectangle, walking the tree depth-first without a stack
+/// by following the parent links back up.
+pub struct QuadtreeQuery<'a> {
+    tree: &'a Quadtree,
+    range: Rect,
+    node: u32,
+    item: u32,
+}
 
-    pub fn query(&self, range: Rect) -> Vec<Box<Circle>> {
-        let mut results = Vec::new();
-        self.root.query(range, &mut results);
-        results
+impl QuadtreeQuery<'_> {
+    /// Move on to the next node (in depth-first order) that overlaps the query range.  Returns
+    /// false once the whole tree has been visited.
+    fn next_node(&mut self) -> bool {
+        let nodes = &self.tree.nodes;
+        let mut node = self.node;
+        let mut descend = true;
+        loop {
+            node = if descend && !nodes[node as usize].is_leaf() {
+                nodes[node as usize].first_child
+            } else {
+                self.tree.next_sibling(node)
+            };
+            if node == NONE {
+                self.node = NONE;
+                return false;
+            }
+            if nodes[node as usize].overlaps(&self.range) {
+                self.node = node;
+                self.item = nodes[node as usize].first_item;
+                return true;
+            }
+            // this whole subtree is outside the range, so skip it
+            descend = false;
+        }
     }
-    pub fn clear(&mut self, bounds: Rect) {
-        self.bounds = bounds;
-        self.root = QuadtreeNode::new(bounds);
+}
+
+impl Iterator for QuadtreeQuery<'_> {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Handle> {
+        if self.node == NONE {
+            return None;
+        }
+        loop {
+            while self.item != NONE {
+                let item = &self.tree.items[self.item as usize];
+                let handle = self.item as Handle;
+                self.item = item.next;
+                if self.range.contains(item.position) {
+                    return Some(handle);
+                }
+            }
+            if !self.next_node() {
+                return None;
+            }
+        }
     }
 }
@@ -0,0 +1,54 @@
+/// A small PCG random number generator (the same algorithm `quad_rand` uses), but as an instance
+/// rather than global state, so that every simulation can be seeded and replayed independently.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+const DEFAULT_INC: u64 = 1442695040888963407;
+
+/// the biggest seed that can be saved in a config file, whose integers are signed 64-bit
+pub const MAX_SEED: u64 = i64::MAX as u64;
+const MULTIPLIER: u64 = 6364136223846793005;
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng { state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    /// Seed from the system clock, for when the user doesn't care about reproducibility.
+    pub fn seed_from_time() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64 & MAX_SEED)
+            .unwrap_or(0)
+    }
+
+    /// returns a pseudo-random number in the range of 0 to u32::MAX.
+    pub fn next_u32(&mut self) -> u32 {
+        let oldstate = self.state;
+        self.state = oldstate.wrapping_mul(MULTIPLIER).wrapping_add(DEFAULT_INC);
+        let xorshifted: u32 = (((oldstate >> 18) ^ oldstate) >> 27) as u32;
+        let rot: u32 = (oldstate >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// returns a pseudo-random number between `low` and `high`.
+    pub fn gen_range(&mut self, low: f32, high: f32) -> f32 {
+        let r = self.next_u32() as f32 / u32::MAX as f32;
+        low + (high - low) * r
+    }
+
+    /// returns a normally distributed pseudo-random number with a mean of 0 and a standard
+    /// deviation of 1.
+    pub fn gen_normal(&mut self) -> f32 {
+        // Box-Muller transform; the logarithm needs something above 0
+        let r1 = self.gen_range(0.0, 1.0).max(f32::MIN_POSITIVE);
+        let r2 = self.gen_range(0.0, 1.0);
+        (-2.0 * r1.ln()).sqrt() * (std::f32::consts::TAU * r2).cos()
+    }
+}
@@ -0,0 +1,278 @@
+use crate::springs::Spring;
+use crate::Circle;
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Something fixed in place that balls bounce off.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Obstacle {
+    /// a thin wall from `start` to `end`
+    Segment {
+        #[serde(with = "vec2_array")]
+        start: Vec2,
+        #[serde(with = "vec2_array")]
+        end: Vec2,
+    },
+    Circle {
+        #[serde(with = "vec2_array")]
+        center: Vec2,
+        radius: f32,
+    },
+    /// a solid box turned `angle` degrees clockwise about its centre, so axis-aligned when
+    /// `angle` is 0
+    Box {
+        #[serde(with = "vec2_array")]
+        center: Vec2,
+        #[serde(with = "vec2_array")]
+        half_size: Vec2,
+        angle: f32,
+    },
+}
+
+impl Obstacle {
+    /// If a ball at `position` with the given radius is overlapping this obstacle, the direction
+    /// to push it out in and how far it has to go.
+    pub fn contact(&self, position: Vec2, radius: f32) -> Option<(Vec2, f32)> {
+        match *self {
+            Obstacle::Segment { start, end } => {
+                let along = end - start;
+                let t = if along == Vec2::ZERO {
+                    0.0
+                } else {
+                    ((position - start).dot(along) / along.length_squared()).clamp(0.0, 1.0)
+                };
+                let offset = position - (start + along * t);
+                let dist = offset.length();
+                if dist >= radius {
+                    return None;
+                }
+                // a ball centred exactly on the wall gets pushed out sideways
+                let normal = if dist > 0.0 {
+                    offset / dist
+                } else {
+                    along.perp().normalize_or_zero()
+                };
+                Some((normal, radius - dist))
+            }
+            Obstacle::Circle {
+                center,
+                radius: obstacle_radius,
+            } => {
+                let offset = position - center;
+                let dist = offset.length();
+                if dist >= radius + obstacle_radius {
+                    return None;
+                }
+                let normal = if dist > 0.0 { offset / dist } else { -Vec2::Y };
+                Some((normal, radius + obstacle_radius - dist))
+            }
+            Obstacle::Box {
+                center,
+                half_size,
+                angle,
+            } => {
+                // work in the box's own frame, where it's axis-aligned around the origin
+                let rotation = Vec2::from_angle(angle.to_radians());
+                let local = Vec2::from_angle(-angle.to_radians()).rotate(position - center);
+                let closest = local.clamp(-half_size, half_size);
+                let (local_normal, depth) = if closest == local {
+                    // inside, so push out through whichever face is nearest
+                    let to_face = half_size - local.abs();
+                    if to_face.x < to_face.y {
+                        (vec2(local.x.signum(), 0.0), to_face.x + radius)
+                    } else {
+                        (vec2(0.0, local.y.signum()), to_face.y + radius)
+                    }
+                } else {
+                    let offset = local - closest;
+                    let dist = offset.length();
+                    if dist >= radius {
+                        return None;
+                    }
+                    (offset / dist, radius - dist)
+                };
+                Some((rotation.rotate(local_normal), depth))
+            }
+        }
+    }
+
+    /// The corners of a box obstacle, going round it; empty for other shapes.
+    pub fn corners(&self) -> Vec<Vec2> {
+        match *self {
+            Obstacle::Box {
+                center,
+                half_size,
+                angle,
+            } => {
+                let rotation = Vec2::from_angle(angle.to_radians());
+                [
+                    vec2(-half_size.x, -half_size.y),
+                    vec2(half_size.x, -half_size.y),
+                    vec2(half_size.x, half_size.y),
+                    vec2(-half_size.x, half_size.y),
+                ]
+                .into_iter()
+                .map(|corner| center + rotation.rotate(corner))
+                .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Shoots out a steady stream of new balls.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Emitter {
+    #[serde(with = "vec2_array")]
+    pub position: Vec2,
+    /// balls per second
+    pub rate: f32,
+    /// which way the balls are fired, in degrees clockwise from the right
+    pub direction: f32,
+    /// how far either side of `direction` balls can go, in degrees
+    pub spread: f32,
+    pub speed: f32,
+    pub min_size: f32,
+    pub max_size: f32,
+    /// the range of hues the balls are coloured with, from 0 to 1
+    pub min_hue: f32,
+    pub max_hue: f32,
+    /// how many balls are due but haven't been fired yet
+    #[serde(skip)]
+    pub pending: f32,
+}
+
+impl Default for Emitter {
+    fn default() -> Self {
+        Emitter {
+            position: Vec2::ZERO,
+            rate: 20.0,
+            direction: 0.0,
+            spread: 20.0,
+            speed: 200.0,
+            min_size: 5.0,
+            max_size: 10.0,
+            min_hue: 0.0,
+            max_hue: 1.0,
+            pending: 0.0,
+        }
+    }
+}
+
+/// Deletes any ball whose centre enters it.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Sink {
+    #[serde(with = "vec2_array")]
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+impl Sink {
+    pub fn swallows(&self, position: Vec2) -> bool {
+        position.distance(self.center) < self.radius
+    }
+}
+
+/// A pair of linked holes: a ball whose centre enters one comes out of the other, going the same
+/// way at the same speed.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Portal {
+    #[serde(with = "vec2_array")]
+    pub a: Vec2,
+    #[serde(with = "vec2_array")]
+    pub b: Vec2,
+    pub radius: f32,
+}
+
+impl Portal {
+    /// Where a ball at `position` moving with `velocity` comes out, if it's gone into either
+    /// end.  It's placed just outside the far end, ahead of itself, so it doesn't go straight
+    /// back in.
+    pub fn teleport(&self, position: Vec2, velocity: Vec2, ball_radius: f32) -> Option<Vec2> {
+        let exit = if position.distance(self.a) < self.radius {
+            self.b
+        } else if position.distance(self.b) < self.radius {
+            self.a
+        } else {
+            return None;
+        };
+        let heading = velocity.try_normalize().unwrap_or(Vec2::Y);
+        Some(exit + heading * (self.radius + ball_radius))
+    }
+}
+
+/// Everything about a simulation's layout that isn't in the config, as saved to and loaded from
+/// `scene.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Scene {
+    /// the balls themselves; a scene without any leaves whatever balls there already are alone
+    #[serde(default)]
+    pub balls: Vec<Circle>,
+    /// the springs and bonds joining the balls together
+    #[serde(default)]
+    pub springs: Vec<Spring>,
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+    #[serde(default)]
+    pub emitters: Vec<Emitter>,
+    #[serde(default)]
+    pub sinks: Vec<Sink>,
+    #[serde(default)]
+    pub portals: Vec<Portal>,
+}
+
+/// Read a scene from `path`, or `None` (after saying why) if it can't be read.
+pub fn load_scene(path: &str) -> Option<Scene> {
+    let scene_str = fs::read_to_string(path)
+        .map_err(|err| {
+            println!("Could not read scene file: {}", err);
+        })
+        .ok()?;
+    toml::from_str(scene_str.as_str())
+        .map_err(|err| {
+            println!("Could not parse scene file: {}", err);
+        })
+        .ok()
+}
+
+pub fn save_scene(scene: &Scene, path: &str) {
+    let scene_str = toml::to_string(scene)
+        .map_err(|err| {
+            println!("Could not serialize scene: {}", err);
+        })
+        .unwrap();
+    let _ = fs::write(path, scene_str.as_str()).map_err(|err| {
+        println!("Could not save scene: {}", err);
+    });
+}
+
+/// Stores a `Vec2` as a two element array, e.g. `[1.0, 2.0]`.
+pub(crate) mod vec2_array {
+    use macroquad::prelude::*;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(vector: &Vec2, serializer: S) -> Result<S::Ok, S::Error> {
+        vector.to_array().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec2, D::Error> {
+        <[f32; 2]>::deserialize(deserializer).map(Vec2::from_array)
+    }
+}
+
+/// Stores a `Color` as a four element array of red, green, blue and alpha, e.g.
+/// `[1.0, 0.5, 0.0, 1.0]`.
+pub(crate) mod color_array {
+    use macroquad::prelude::*;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        color.to_vec().to_array().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        <[f32; 4]>::deserialize(deserializer).map(Color::from)
+    }
+}
@@ -0,0 +1,1755 @@
+use crate::config::JiggleBallsConfig;
+use crate::fluid::{density_kernel, pressure_kernel_slope, viscosity_kernel_laplacian};
+use crate::quadtree::Quadtree;
+use crate::rng::Rng;
+use crate::scene::{Emitter, Obstacle, Portal, Scene, Sink};
+use crate::spatial::{quadtree_settings, AnySpatialIndex, Neighbour, SpatialIndex, Wraparound};
+use crate::springs::Spring;
+use crate::{bounding_box, gen_circle, Circle, CircleId};
+use macroquad::color::{hsl_to_rgb, rgb_to_hsl};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Everything the simulation needs to know about the user's input for a single step.  The front
+/// end is responsible for filling this in, so the simulation itself never touches macroquad's
+/// input functions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputState {
+    pub mouse_position: Vec2,
+    /// how far the mouse moved since the previous frame
+    pub mouse_delta: Vec2,
+    pub left_mouse_down: bool,
+    pub right_mouse_down: bool,
+    /// whether the mouse should push/pull balls (e.g. disabled while the gui is on screen)
+    pub mouse_forces_enabled: bool,
+    /// which way to turn gravity when tilting with the keys: -1 for anticlockwise, 1 for
+    /// clockwise, 0 to leave it alone
+    pub tilt: f32,
+}
+
+/// Things that happened during a step that a front end may want to react to (e.g. by playing a
+/// sound), since the simulation does no audio or drawing itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimulationEvent {
+    /// a ball of the given size bounced off one of the walls
+    WallHit { circle_size: f32 },
+}
+
+/// Two balls that hit each other hard enough to merge or break up, found during a step and dealt
+/// with once it's over.
+struct Impact {
+    a: CircleId,
+    b: CircleId,
+    speed: f32,
+}
+
+/// How balls that touch each other react.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionModel {
+    /// overlapping balls are pushed apart in proportion to how far they overlap
+    #[default]
+    SoftRepel,
+    /// balls bounce off each other like billiard balls, conserving momentum and losing energy
+    /// according to `restitution`
+    Elastic,
+}
+
+/// How balls' positions and velocities are advanced each step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    /// move by the velocity from the start of the step, then apply this step's forces
+    #[default]
+    ExplicitEuler,
+    /// apply this step's forces first, then move by the updated velocity; much more stable
+    SemiImplicitEuler,
+    /// like semi-implicit Euler, but afterwards each ball's velocity is set to however far it
+    /// actually moved over the step (including being pushed out of other balls), divided by the
+    /// step; obstacles and walls then bounce that velocity as usual
+    PositionVerlet,
+}
+
+impl Integrator {
+    pub const ALL: [Integrator; 3] = [
+        Integrator::ExplicitEuler,
+        Integrator::SemiImplicitEuler,
+        Integrator::PositionVerlet,
+    ];
+}
+
+/// How the balls are jiggled about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JiggleModel {
+    /// every step, each ball's velocity is kicked by a random amount of up to `jiggle` in each
+    /// direction, so how much the balls jiggle depends on `physics_hz`
+    #[default]
+    Uniform,
+    /// Langevin dynamics: balls are kicked about by Gaussian noise that grows with the square
+    /// root of the step and slowed down by `brownian_damping`, so that, whatever the step size,
+    /// the mean square of how far they've wandered grows by 4 × `diffusion` every second
+    Brownian,
+}
+
+impl JiggleModel {
+    pub const ALL: [JiggleModel; 2] = [JiggleModel::Uniform, JiggleModel::Brownian];
+}
+
+/// What, if anything, turns the direction of gravity while the simulation runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GravityTilt {
+    /// gravity stays pointing at `gravity_angle`
+    #[default]
+    Off,
+    /// `InputState::tilt` turns gravity round
+    Keys,
+    /// gravity points from the middle of the window towards the mouse
+    Mouse,
+}
+
+impl GravityTilt {
+    pub const ALL: [GravityTilt; 3] = [GravityTilt::Off, GravityTilt::Keys, GravityTilt::Mouse];
+}
+
+/// What happens to a ball that reaches one of the edges of the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+    /// the ball bounces back, keeping `wall_restitution` of its speed
+    #[default]
+    Reflect,
+    /// the ball goes out through the edge and comes back in through the opposite one
+    Wrap,
+    /// the ball is deleted as soon as it touches the edge
+    Absorb,
+    /// the ball is deleted as soon as it touches the edge, and a new one is fired from one of
+    /// the emitters (or placed at random, if there aren't any) to take its place
+    Respawn,
+    /// there's no wall at all, so balls can fly out of the window and come back again, though
+    /// they're deleted once they get too far away
+    Open,
+}
+
+impl BoundaryMode {
+    pub const ALL: [BoundaryMode; 5] = [
+        BoundaryMode::Reflect,
+        BoundaryMode::Wrap,
+        BoundaryMode::Absorb,
+        BoundaryMode::Respawn,
+        BoundaryMode::Open,
+    ];
+
+    /// How the `low` and `high` edges of one axis actually behave.  A ball that wraps round has
+    /// to come back in through the opposite edge, so if either edge wraps, both do.
+    pub fn axis(low: BoundaryMode, high: BoundaryMode) -> (BoundaryMode, BoundaryMode) {
+        if low == BoundaryMode::Wrap || high == BoundaryMode::Wrap {
+            (BoundaryMode::Wrap, BoundaryMode::Wrap)
+        } else {
+            (low, high)
+        }
+    }
+}
+
+/// how far outside the window a ball can get through an open edge before it's deleted
+const OPEN_BOUNDARY_MARGIN: f32 = 1000.0;
+
+/// Apply the boundary modes of the `low` and `high` edges along one axis, which runs from 0 to
+/// `length`, to a ball of the given radius at `position` moving at `velocity` along it.  Returns
+/// whether the ball bounced, whether it wrapped round to the other edge, and the mode of the edge
+/// it has left through, if it should be deleted.
+fn cross_edges(
+    position: &mut f32,
+    velocity: &mut f32,
+    radius: f32,
+    length: f32,
+    (low, high): (BoundaryMode, BoundaryMode),
+    restitution: f32,
+) -> (bool, bool, Option<BoundaryMode>) {
+    let mut bounced = false;
+    let mut wrapped = false;
+    let mut leaving = None;
+    // `outwards` is which way along the axis is out through the edge
+    for (mode, outwards, touching, beyond) in [
+        (low, -1.0, *position <= radius, -*position),
+        (high, 1.0, *position >= length - radius, *position - length),
+    ] {
+        match mode {
+            BoundaryMode::Reflect => {
+                if touching {
+                    bounced = true;
+                    *velocity = -(*velocity * restitution);
+                    // make the wall actively "push" the ball away
+                    *velocity -= outwards;
+                }
+            }
+            BoundaryMode::Wrap => {
+                if beyond > 0.0 {
+                    wrapped = true;
+                    *position = position.rem_euclid(length);
+                }
+            }
+            BoundaryMode::Absorb | BoundaryMode::Respawn => {
+                if touching {
+                    leaving = Some(mode);
+                }
+            }
+            BoundaryMode::Open => {
+                if beyond > OPEN_BOUNDARY_MARGIN {
+                    leaving = Some(mode);
+                }
+            }
+        }
+    }
+    (bounced, wrapped, leaving)
+}
+
+/// One of the four walls of the window, or none of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Wall {
+    #[default]
+    None,
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Wall {
+    pub const ALL: [Wall; 5] = [Wall::None, Wall::Left, Wall::Right, Wall::Top, Wall::Bottom];
+
+    /// The strip `depth` wide running along this wall of a `width`x`height` window.
+    pub fn region(self, depth: f32, width: f32, height: f32) -> Option<Rect> {
+        match self {
+            Wall::None => None,
+            Wall::Left => Some(Rect::new(0.0, 0.0, depth, height)),
+            Wall::Right => Some(Rect::new(width - depth, 0.0, depth, height)),
+            Wall::Top => Some(Rect::new(0.0, 0.0, width, depth)),
+            Wall::Bottom => Some(Rect::new(0.0, height - depth, width, depth)),
+        }
+    }
+}
+
+/// How to colour a ball at `temperature`, running from blue when it's at `cold` to red when
+/// it's at `hot`.
+pub fn temperature_color(temperature: f32, cold: f32, hot: f32) -> Color {
+    let warmth = ((temperature - cold) / (hot - cold).max(f32::EPSILON)).clamp(0.0, 1.0);
+    hsl_to_rgb(2.0 / 3.0 * (1.0 - warmth), 0.8, 0.5)
+}
+
+/// how fast holding down a tilt key turns gravity, in degrees per second
+const GRAVITY_TILT_SPEED: f32 = 90.0;
+
+/// the most particle life species there can be
+pub const MAX_SPECIES: u32 = 8;
+
+/// closer than this fraction of `particle_life_radius`, every species pushes every other away
+const PARTICLE_LIFE_REPEL_DISTANCE: f32 = 0.3;
+
+/// Which of `num_species` particle life species a ball belongs to, going by its hue.
+fn species_of(circ: &Circle, num_species: usize) -> usize {
+    let (hue, _, _) = rgb_to_hsl(circ.color);
+    ((hue * num_species as f32) as usize).min(num_species - 1)
+}
+
+/// How hard a particle life ball is pulled towards another (or pushed away, if negative), where
+/// `distance` is a fraction of `particle_life_radius`.  Very close balls always repel so they
+/// don't collapse into a point; further out the pull rises to `attraction` half way between
+/// there and the edge of the radius, and falls back to nothing at the edge.
+fn particle_life_force(distance: f32, attraction: f32) -> f32 {
+    if distance < PARTICLE_LIFE_REPEL_DISTANCE {
+        distance / PARTICLE_LIFE_REPEL_DISTANCE - 1.0
+    } else if distance < 1.0 {
+        attraction
+            * (1.0
+                - (2.0 * distance - 1.0 - PARTICLE_LIFE_REPEL_DISTANCE).abs()
+                    / (1.0 - PARTICLE_LIFE_REPEL_DISTANCE))
+    } else {
+        0.0
+    }
+}
+
+/// how close two balls have to be for one to stick to the other, as a multiple of the distance
+/// at which they'd touch
+const STICKY_REACH: f32 = 1.2;
+
+/// `stickiness` is the chance of bonding over a step this many times a second, so that balls
+/// stick at the same rate whatever `physics_hz` is
+const STICKINESS_HZ: f32 = 60.0;
+
+/// A pair of balls in a consistent order, for looking up whether they're joined.
+fn ordered_pair(a: CircleId, b: CircleId) -> (CircleId, CircleId) {
+    (a.min(b), a.max(b))
+}
+
+/// how fast the pieces of a broken up ball fly apart, as a fraction of the impact speed
+const FRAGMENT_SCATTER: f32 = 0.25;
+
+/// the shortest lifetime a ball can be given, in seconds
+const MIN_LIFETIME: f32 = 0.1;
+
+/// emitters stop firing and balls stop breaking up once there are this many balls (the most
+/// the gui's ball count slider allows)
+const MAX_CIRCLES: usize = 15_000;
+
+/// how far apart the centres of neighbouring balls in a soft body are, in ball radii, leaving a
+/// small gap so they don't start off pushing each other away
+const BODY_SPACING: f32 = 2.2;
+
+/// how far balls may overlap before the elastic model's positional correction kicks in
+const COLLISION_SLOP: f32 = 0.01;
+
+pub struct Simulation {
+    pub circles: Vec<Circle>,
+    /// springs joining pairs of balls; any whose balls have gone are dropped on the next step
+    pub springs: Vec<Spring>,
+    /// fixed shapes the balls bounce off, kept when the balls are reset
+    pub obstacles: Vec<Obstacle>,
+    pub emitters: Vec<Emitter>,
+    pub sinks: Vec<Sink>,
+    pub portals: Vec<Portal>,
+    pub spatial_index: AnySpatialIndex,
+    /// holds every ball's mass for the Barnes-Hut approximation when `config.nbody` is on,
+    /// whichever spatial index is in use for finding neighbours
+    nbody_tree: Quadtree,
+    pub config: JiggleBallsConfig,
+    pub starfield_origin: Vec2,
+    rng: Rng,
+    seed: u64,
+    next_id: u64,
+    width: f32,
+    height: f32,
+    /// unsimulated time carried over between frames by [`Simulation::advance`]
+    accumulator: f32,
+    /// where each ball was before the most recent step, for interpolating between physics states
+    previous_positions: Vec<Vec2>,
+    /// which particle life species each ball belongs to, worked out at the start of each step
+    species: Vec<usize>,
+    /// how dense the fluid is around each ball, worked out at the start of each step
+    densities: Vec<f32>,
+    /// how much the springs accelerate each ball this step
+    spring_accelerations: Vec<Vec2>,
+    /// mouse movement that hasn't been fed into a step yet
+    pending_mouse_delta: Vec2,
+}
+
+impl Simulation {
+    pub fn new(config: JiggleBallsConfig, width: f32, height: f32) -> Self {
+        let mut simulation = Simulation {
+            circles: Vec::new(),
+            springs: Vec::new(),
+            obstacles: Vec::new(),
+            emitters: Vec::new(),
+            sinks: Vec::new(),
+            portals: Vec::new(),
+            spatial_index: AnySpatialIndex::new(bounding_box(width, height), &config),
+            nbody_tree: Quadtree::with_settings(
+                bounding_box(width, height),
+                quadtree_settings(&config),
+            ),
+            config,
+            starfield_origin: vec2(width / 2.0, height / 2.0),
+            rng: Rng::new(0),
+            seed: 0,
+            next_id: 0,
+            width,
+            height,
+            accumulator: 0.0,
+            previous_positions: Vec::new(),
+            species: Vec::new(),
+            densities: Vec::new(),
+            spring_accelerations: Vec::new(),
+            pending_mouse_delta: Vec2::ZERO,
+        };
+        simulation.reset();
+        simulation
+    }
+
+    /// Update the size of the area the balls are confined to, e.g. after the window is resized.
+    pub fn resize(&mut self, width: f32, height: f32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// The seed the current run was started from; passing it back in through `config.seed`
+    /// reproduces the run exactly, given the same inputs.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Hand out an id that no other ball in this simulation has had.
+    pub fn next_circle_id(&mut self) -> CircleId {
+        let id = CircleId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// A new ball with a fresh id, placed and sized at random as configured.
+    fn random_circle(&mut self) -> Circle {
+        let id = self.next_circle_id();
+        gen_circle(
+            &mut self.rng,
+            id,
+            self.width,
+            self.height,
+            self.config.min_circle_size,
+            self.config.max_circle_size,
+            self.config.charged_fraction,
+        )
+    }
+
+    /// Throw away all the balls and generate a fresh set of `config.num_circles`.  The random
+    /// number generator is re-seeded from `config.seed`, or from the clock if that isn't set.
+    pub fn reset(&mut self) {
+        self.seed = self.config.seed.unwrap_or_else(Rng::seed_from_time);
+        self.rng = Rng::new(self.seed);
+        self.circles.clear();
+        self.springs.clear();
+        self.next_id = 0;
+        for _i in 0..self.config.num_circles {
+            let circle = self.random_circle();
+            self.circles.push(circle);
+        }
+        self.spatial_index
+            .clear(bounding_box(self.width, self.height));
+        self.starfield_origin = vec2(self.width / 2.0, self.height / 2.0);
+        self.previous_positions.clear();
+        self.accumulator = 0.0;
+    }
+
+    /// The parts of the simulation that make up a saved scene.
+    pub fn scene(&self) -> Scene {
+        Scene {
+            balls: self.circles.clone(),
+            springs: self.springs.clone(),
+            obstacles: self.obstacles.clone(),
+            emitters: self.emitters.clone(),
+            sinks: self.sinks.clone(),
+            portals: self.portals.clone(),
+        }
+    }
+
+    /// Replace the current scene with `scene`.  The balls (and the springs between them) are
+    /// only replaced if the scene has some.
+    pub fn load_scene(&mut self, scene: Scene) {
+        if !scene.balls.is_empty() {
+            self.circles = scene.balls;
+            self.springs = scene.springs;
+            self.next_id = self
+                .circles
+                .iter()
+                .map(|circ| circ.id.0 + 1)
+                .max()
+                .unwrap_or(0);
+            self.config.num_circles = self.circles.len() as u32;
+            self.config.num_circles_ui = self.circles.len() as f32;
+            self.previous_positions.clear();
+        }
+        self.obstacles = scene.obstacles;
+        self.emitters = scene.emitters;
+        self.sinks = scene.sinks;
+        self.portals = scene.portals;
+    }
+
+    /// Fire any balls the emitters are due to fire over the next `delta_time` seconds, and
+    /// delete any balls that have gone into a sink.
+    fn emit_and_sink(&mut self, delta_time: f32) {
+        for index in 0..self.emitters.len() {
+            self.emitters[index].pending += self.emitters[index].rate * delta_time;
+            let emitter = self.emitters[index];
+            let mut fired = 0.0;
+            while emitter.pending - fired >= 1.0 && self.circles.len() < MAX_CIRCLES {
+                self.fire(&emitter);
+                fired += 1.0;
+            }
+            // don't let balls build up while the simulation is full
+            self.emitters[index].pending = (emitter.pending - fired).min(1.0);
+        }
+
+        if !self.sinks.is_empty() {
+            let sunk: Vec<bool> = self
+                .circles
+                .iter()
+                .map(|circ| self.sinks.iter().any(|sink| sink.swallows(circ.position)))
+                .collect();
+            self.forget_removed_balls(&sunk);
+        }
+    }
+
+    /// Fire a single ball out of `emitter`.
+    fn fire(&mut self, emitter: &Emitter) {
+        let angle = emitter.direction
+            + self
+                .rng
+                .gen_range(-emitter.spread / 2.0, emitter.spread / 2.0);
+        let hue = self.rng.gen_range(emitter.min_hue, emitter.max_hue);
+        let size = self.rng.gen_range(emitter.min_size, emitter.max_size);
+        self.spawn_ball(emitter.position, size, hsl_to_rgb(hue, 0.5, 0.5));
+        // spawn_ball pushes onto the end
+        self.circles.last_mut().unwrap().velocity =
+            Vec2::from_angle(angle.to_radians()) * emitter.speed;
+    }
+
+    /// Delete the balls in `leaving` that went out through an edge that absorbs, respawns or is
+    /// open, and fire new balls from the emitters in place of the ones that respawn.
+    fn remove_leaving_balls(&mut self, leaving: &[(CircleId, BoundaryMode)]) {
+        if leaving.is_empty() {
+            return;
+        }
+        let ids: HashSet<CircleId> = leaving.iter().map(|(id, _)| *id).collect();
+        let gone: Vec<bool> = self
+            .circles
+            .iter()
+            .map(|circ| ids.contains(&circ.id))
+            .collect();
+        self.forget_removed_balls(&gone);
+
+        let respawns = leaving
+            .iter()
+            .filter(|(_, mode)| *mode == BoundaryMode::Respawn)
+            .count();
+        for _ in 0..respawns {
+            self.respawn();
+        }
+    }
+
+    /// How the window wraps round, going by which edges are set to wrap.
+    pub fn wraparound(&self) -> Wraparound {
+        let wraps = |low, high| BoundaryMode::axis(low, high).0 == BoundaryMode::Wrap;
+        Wraparound {
+            bounds: bounding_box(self.width, self.height),
+            x: !self.config.starfield
+                && wraps(self.config.boundary_left, self.config.boundary_right),
+            y: !self.config.starfield
+                && wraps(self.config.boundary_top, self.config.boundary_bottom),
+        }
+    }
+
+    /// Delete the balls marked in `gone`, which lines up with `circles`, and stop counting them
+    /// towards `config.num_circles` so they aren't put straight back by `sync_circle_count`.
+    /// Returns how many were deleted.
+    fn forget_removed_balls(&mut self, gone: &[bool]) -> usize {
+        let removed = gone.iter().filter(|gone| **gone).count();
+        if removed == 0 {
+            return 0;
+        }
+        let mut flags = gone.iter();
+        self.circles
+            .retain(|_| !flags.next().copied().unwrap_or(false));
+        // previous_positions has to stay lined up with circles for interpolation; any balls
+        // added on the end since the last snapshot don't have a previous position yet
+        let mut flags = gone.iter();
+        self.previous_positions
+            .retain(|_| !flags.next().copied().unwrap_or(false));
+        self.config.num_circles = self.config.num_circles.saturating_sub(removed as u32);
+        self.config.num_circles_ui = (self.config.num_circles_ui - removed as f32).max(0.0);
+        removed
+    }
+
+    /// Merge or break up the balls in each of `impacts`, depending on how hard they hit.  A ball
+    /// only takes part in the first impact it's in each step.
+    fn resolve_impacts(&mut self, impacts: &[Impact]) {
+        if impacts.is_empty() {
+            return;
+        }
+        let indices = self.circle_indices();
+        let mut involved = HashSet::new();
+        let mut merged_away = vec![false; self.circles.len()];
+        for impact in impacts {
+            if involved.contains(&impact.a) || involved.contains(&impact.b) {
+                continue;
+            }
+            let (a, b) = (indices[&impact.a], indices[&impact.b]);
+            if self.config.fragmenting && impact.speed >= self.config.fragment_speed {
+                // the energy of the impact goes into breaking up, so everything that's left
+                // carries on together, and the pieces don't just hit the other ball again
+                let (first, second) = (self.circles[a], self.circles[b]);
+                let shared_velocity = (first.velocity * first.mass + second.velocity * second.mass)
+                    / (first.mass + second.mass);
+                let broke_a = self.break_up(a, shared_velocity, impact.speed);
+                let broke_b = self.break_up(b, shared_velocity, impact.speed);
+                if !broke_a && !broke_b {
+                    continue;
+                }
+                if !broke_a {
+                    self.circles[a].velocity = shared_velocity;
+                }
+                if !broke_b {
+                    self.circles[b].velocity = shared_velocity;
+                }
+            } else if self.config.merging && impact.speed >= self.config.merge_speed {
+                match self.merge(a, b) {
+                    Some(gone) => merged_away[gone] = true,
+                    None => continue,
+                }
+            } else {
+                continue;
+            }
+            involved.insert(impact.a);
+            involved.insert(impact.b);
+        }
+
+        self.forget_removed_balls(&merged_away);
+    }
+
+    /// Give each pair of touching balls in `contacts` that isn't already joined a chance of
+    /// bonding over a step of `delta_time`, with a spring that snaps if it's stretched too far.
+    /// Both balls become sticky, so clumps keep growing as more balls run into them.
+    fn form_bonds(&mut self, contacts: &[(CircleId, CircleId)], delta_time: f32) {
+        if contacts.is_empty() {
+            return;
+        }
+        // bonding over this step is not failing to bond in every sixtieth of a second it covers
+        let stickiness = self.config.stickiness.clamp(0.0, 1.0);
+        let chance = 1.0 - (1.0 - stickiness).powf(delta_time * STICKINESS_HZ);
+        let indices = self.circle_indices();
+        let mut joined: HashSet<(CircleId, CircleId)> = self
+            .springs
+            .iter()
+            .map(|spring| ordered_pair(spring.a, spring.b))
+            .collect();
+        for &(a, b) in contacts {
+            // either ball might have just been merged away
+            let (Some(&index_a), Some(&index_b)) = (indices.get(&a), indices.get(&b)) else {
+                continue;
+            };
+            if joined.contains(&(a, b)) || self.rng.gen_range(0.0, 1.0) >= chance {
+                continue;
+            }
+            joined.insert((a, b));
+            self.springs.push(Spring {
+                a,
+                b,
+                rest_length: self.circles[index_a].radius + self.circles[index_b].radius,
+                stiffness: self.config.bond_stiffness,
+                damping: self.config.bond_damping,
+                break_stretch: Some(self.config.bond_break_stretch),
+            });
+            self.circles[index_a].sticky = true;
+            self.circles[index_b].sticky = true;
+        }
+    }
+
+    /// Combine the balls at `a` and `b` into one with the same total area and momentum, in
+    /// place of the bigger of the two, and return the index of the one that should be removed.
+    /// Nothing happens if the merged ball would be bigger than `max_circle_size`.
+    fn merge(&mut self, a: usize, b: usize) -> Option<usize> {
+        let (first, second) = (self.circles[a], self.circles[b]);
+        let area = first.radius * first.radius + second.radius * second.radius;
+        let radius = area.sqrt();
+        if radius > self.config.max_circle_size {
+            return None;
+        }
+        let (keep, gone) = if first.radius >= second.radius {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        let mass = first.mass + second.mass;
+        let share = second.radius * second.radius / area;
+        self.circles[keep] = Circle {
+            position: (first.position * first.mass + second.position * second.mass) / mass,
+            radius,
+            color: Color::from_vec(first.color.to_vec().lerp(second.color.to_vec(), share)),
+            velocity: (first.velocity * first.mass + second.velocity * second.mass) / mass,
+            mass,
+            charge: first.charge + second.charge,
+            temperature: (first.temperature * first.mass + second.temperature * second.mass) / mass,
+            ..self.circles[keep]
+        };
+        Some(gone)
+    }
+
+    /// Break the ball at `index` into up to `config.fragment_count` equal pieces with the same
+    /// total area, flying apart from `velocity` at a speed proportional to `impact_speed`.  The
+    /// first piece takes the ball's place and the rest are added to the end.  Returns false,
+    /// leaving the ball alone, if it's too small for the pieces to be at least
+    /// `min_circle_size` or there's no room for any more balls.
+    fn break_up(&mut self, index: usize, velocity: Vec2, impact_speed: f32) -> bool {
+        let circ = self.circles[index];
+        let fit = (circ.radius / self.config.min_circle_size).powi(2);
+        let count = (self.config.fragment_count as f32).min(fit).floor() as u32;
+        if count < 2 || self.circles.len() + count as usize > MAX_CIRCLES {
+            return false;
+        }
+        let piece_radius = circ.radius / (count as f32).sqrt();
+        // spread round the middle, as far out as they can go while staying inside the ball
+        let spacing = circ.radius - piece_radius;
+        let turn = self.rng.gen_range(0.0, std::f32::consts::TAU);
+        for piece in 0..count {
+            let direction =
+                Vec2::from_angle(turn + std::f32::consts::TAU * piece as f32 / count as f32);
+            let id = if piece == 0 {
+                circ.id
+            } else {
+                self.next_circle_id()
+            };
+            let fragment = Circle {
+                id,
+                position: circ.position + direction * spacing,
+                radius: piece_radius,
+                velocity: velocity + direction * impact_speed * FRAGMENT_SCATTER,
+                mass: circ.mass / count as f32,
+                charge: circ.charge / count as f32,
+                ..circ
+            };
+            if piece == 0 {
+                self.circles[index] = fragment;
+            } else {
+                self.circles.push(fragment);
+            }
+        }
+        self.config.num_circles += count - 1;
+        self.config.num_circles_ui += (count - 1) as f32;
+        true
+    }
+
+    /// Put a new ball in place of one that's gone: fired from one of the emitters if there are
+    /// any, or otherwise placed at random (or at the middle of a starfield).
+    fn respawn(&mut self) {
+        if self.emitters.is_empty() {
+            let mut circle = self.random_circle();
+            if self.config.starfield {
+                circle.position = self.starfield_origin;
+            }
+            self.circles.push(circle);
+            self.config.num_circles += 1;
+            self.config.num_circles_ui += 1.0;
+        } else {
+            let pick = self.rng.gen_range(0.0, self.emitters.len() as f32) as usize;
+            let emitter = self.emitters[pick.min(self.emitters.len() - 1)];
+            self.fire(&emitter);
+        }
+    }
+
+    /// Age every ball by `delta_time`, giving a lifetime to any that don't have one yet, and
+    /// remove (or replace) the ones that have expired.
+    fn age_and_expire(&mut self, delta_time: f32) {
+        let (min_lifetime, max_lifetime) = (self.config.min_lifetime, self.config.max_lifetime);
+        for circ in &mut self.circles {
+            if circ.lifetime <= 0.0 {
+                circ.lifetime = self
+                    .rng
+                    .gen_range(min_lifetime, max_lifetime)
+                    .max(MIN_LIFETIME);
+            }
+            circ.age += delta_time;
+        }
+
+        let gone: Vec<bool> = self
+            .circles
+            .iter()
+            .map(|circ| circ.age >= circ.lifetime)
+            .collect();
+        let expired = self.forget_removed_balls(&gone);
+        if !self.config.respawn_expired {
+            return;
+        }
+        for _ in 0..expired {
+            self.respawn();
+            // respawn pushes onto the end
+            self.circles.last_mut().unwrap().lifetime = self
+                .rng
+                .gen_range(min_lifetime, max_lifetime)
+                .max(MIN_LIFETIME);
+        }
+    }
+
+    /// How big and what colour to draw `circ`, once it's been aged as configured.
+    pub fn appearance(&self, circ: &Circle) -> (f32, Color) {
+        let mut color = if self.config.color_by_temperature {
+            temperature_color(
+                circ.temperature,
+                self.config.cold_temperature,
+                self.config.hot_temperature,
+            )
+        } else {
+            circ.color
+        };
+        if !self.config.lifetimes {
+            return (circ.radius, color);
+        }
+        let life = circ.life_fraction();
+        let mut radius = circ.radius;
+        if self.config.age_hue_shift != 0.0 {
+            let (hue, saturation, lightness) = rgb_to_hsl(color);
+            let shifted = (hue + life * self.config.age_hue_shift).rem_euclid(1.0);
+            color = Color {
+                a: color.a,
+                ..hsl_to_rgb(shifted, saturation, lightness)
+            };
+        }
+        if self.config.age_fade {
+            color.a *= 1.0 - life;
+        }
+        if self.config.age_shrink {
+            radius *= 1.0 - life;
+        }
+        (radius, color)
+    }
+
+    /// The average temperature of all the balls, or 0 if there aren't any.
+    pub fn mean_temperature(&self) -> f32 {
+        if self.circles.is_empty() {
+            return 0.0;
+        }
+        self.circles
+            .iter()
+            .map(|circ| circ.temperature)
+            .sum::<f32>()
+            / self.circles.len() as f32
+    }
+
+    /// Where each ball is in `circles`, by id.
+    fn circle_indices(&self) -> HashMap<CircleId, usize> {
+        self.circles
+            .iter()
+            .enumerate()
+            .map(|(index, circ)| (circ.id, index))
+            .collect()
+    }
+
+    /// The two ends of every spring, at the positions the balls should be drawn at.
+    pub fn spring_lines(&self) -> Vec<(Vec2, Vec2)> {
+        let indices = self.circle_indices();
+        self.springs
+            .iter()
+            .filter_map(|spring| {
+                let a = *indices.get(&spring.a)?;
+                let b = *indices.get(&spring.b)?;
+                Some((self.interpolated_position(a), self.interpolated_position(b)))
+            })
+            .collect()
+    }
+
+    /// Add a ball on top of the randomly placed ones, counting it towards `config.num_circles`
+    /// so it isn't removed again straight away.
+    fn spawn_ball(&mut self, position: Vec2, radius: f32, color: Color) -> CircleId {
+        let id = self.next_circle_id();
+        self.circles.push(Circle::new(id, position, radius, color));
+        self.config.num_circles += 1;
+        self.config.num_circles_ui += 1.0;
+        id
+    }
+
+    /// Join two balls with a spring that's at rest at their current distance apart, using the
+    /// configured stiffness and damping.
+    pub fn connect(&mut self, a: CircleId, b: CircleId) {
+        let indices = self.circle_indices();
+        let (Some(&index_a), Some(&index_b)) = (indices.get(&a), indices.get(&b)) else {
+            return;
+        };
+        self.springs.push(Spring {
+            a,
+            b,
+            rest_length: self.circles[index_a]
+                .position
+                .distance(self.circles[index_b].position),
+            stiffness: self.config.spring_stiffness,
+            damping: self.config.spring_damping,
+            break_stretch: None,
+        });
+    }
+
+    fn random_body_color(&mut self) -> Color {
+        hsl_to_rgb(self.rng.gen_range(0.0, 1.0), 0.5, 0.5)
+    }
+
+    /// Spawn a rope of balls from `start` to `end`, each joined to the next.
+    pub fn spawn_rope(&mut self, start: Vec2, end: Vec2, ball_radius: f32) -> Vec<CircleId> {
+        let color = self.random_body_color();
+        let segments = (start.distance(end) / (ball_radius * BODY_SPACING)).max(1.0) as usize;
+        let ids: Vec<CircleId> = (0..=segments)
+            .map(|i| {
+                let position = start.lerp(end, i as f32 / segments as f32);
+                self.spawn_ball(position, ball_radius, color)
+            })
+            .collect();
+        for pair in ids.windows(2) {
+            self.connect(pair[0], pair[1]);
+        }
+        ids
+    }
+
+    /// Spawn a ring of balls around `center`, each joined to its neighbours and to the balls
+    /// two along so the ring resists bending.
+    pub fn spawn_ring(
+        &mut self,
+        center: Vec2,
+        ring_radius: f32,
+        ball_radius: f32,
+    ) -> Vec<CircleId> {
+        let color = self.random_body_color();
+        let circumference = 2.0 * std::f32::consts::PI * ring_radius;
+        let count = ((circumference / (ball_radius * BODY_SPACING)) as usize).max(3);
+        let ids: Vec<CircleId> = (0..count)
+            .map(|i| {
+                let angle = i as f32 / count as f32 * std::f32::consts::TAU;
+                let position = center + Vec2::from_angle(angle) * ring_radius;
+                self.spawn_ball(position, ball_radius, color)
+            })
+            .collect();
+        for i in 0..count {
+            self.connect(ids[i], ids[(i + 1) % count]);
+            if count > 4 {
+                self.connect(ids[i], ids[(i + 2) % count]);
+            }
+        }
+        ids
+    }
+
+    /// Spawn a jelly blob: a ring of balls with a ball in the middle joined to every one of them.
+    pub fn spawn_blob(
+        &mut self,
+        center: Vec2,
+        blob_radius: f32,
+        ball_radius: f32,
+    ) -> Vec<CircleId> {
+        let mut ids = self.spawn_ring(center, blob_radius, ball_radius);
+        let color = self.circles[self.circles.len() - 1].color;
+        let middle = self.spawn_ball(center, ball_radius, color);
+        for &rim in &ids {
+            self.connect(middle, rim);
+        }
+        ids.push(middle);
+        ids
+    }
+
+    /// Fill the particle life attraction matrix with random strengths between -1 and 1.
+    pub fn randomize_particle_life_matrix(&mut self) {
+        let num_species = self.config.particle_life_species as usize;
+        self.config.particle_life_matrix = (0..num_species)
+            .map(|_| {
+                (0..num_species)
+                    .map(|_| self.rng.gen_range(-1.0, 1.0))
+                    .collect()
+            })
+            .collect();
+    }
+
+    /// Add or remove balls so that there are (roughly) `config.num_circles` of them.
+    pub fn sync_circle_count(&mut self) {
+        let num_circles = self.config.num_circles as usize;
+        if self.circles.len() < num_circles {
+            for _ in 1..num_circles - self.circles.len() {
+                let circle = self.random_circle();
+                self.circles.push(circle);
+            }
+        } else if self.circles.len() > num_circles {
+            self.circles.drain(num_circles..);
+        }
+    }
+
+    /// The length of a single physics step, in seconds.
+    pub fn fixed_delta_time(&self) -> f32 {
+        1.0 / self.config.physics_hz.max(1.0)
+    }
+
+    /// Advance the simulation by however many fixed-size steps fit into `frame_time` (plus any
+    /// time left over from previous frames), so the physics behave the same regardless of frame
+    /// rate.  At most `config.max_substeps` steps are taken, so a long hitch slows the simulation
+    /// down rather than making it explode.
+    pub fn advance(&mut self, frame_time: f32, input: &InputState) -> Vec<SimulationEvent> {
+        let delta_time = self.fixed_delta_time();
+        let mut events = Vec::new();
+        let mut step_input = *input;
+        self.pending_mouse_delta += input.mouse_delta;
+        self.accumulator += frame_time;
+        let mut substeps = 0;
+        while self.accumulator >= delta_time {
+            if substeps >= self.config.max_substeps {
+                // drop the time we couldn't catch up on
+                self.accumulator = 0.0;
+                break;
+            }
+            step_input.mouse_delta = self.pending_mouse_delta;
+            self.pending_mouse_delta = Vec2::ZERO;
+            events.append(&mut self.step(delta_time, &step_input));
+            self.accumulator -= delta_time;
+            substeps += 1;
+        }
+        events
+    }
+
+    /// How far between the previous and the current physics state the next frame should be
+    /// drawn, from 0 (previous) to 1 (current).
+    pub fn interpolation_alpha(&self) -> f32 {
+        (self.accumulator / self.fixed_delta_time()).clamp(0.0, 1.0)
+    }
+
+    /// Where to draw the ball at `index`, blending its previous and current position by
+    /// [`Simulation::interpolation_alpha`].  Balls that didn't exist before the last step, or
+    /// that jumped somewhere else during it, are drawn where they are.
+    pub fn interpolated_position(&self, index: usize) -> Vec2 {
+        let position = self.circles[index].position;
+        match self.previous_positions.get(index) {
+            Some(previous) => previous.lerp(position, self.interpolation_alpha()),
+            None => position,
+        }
+    }
+
+    /// Advance the simulation by exactly `delta_time` seconds.  Does no drawing and no audio;
+    /// anything a front end might want to react to is returned as a list of events.
+    pub fn step(&mut self, delta_time: f32, input: &InputState) -> Vec<SimulationEvent> {
+        let mut events = Vec::new();
+        self.emit_and_sink(delta_time);
+        if self.config.lifetimes {
+            self.age_and_expire(delta_time);
+        }
+        for circ in &mut self.circles {
+            circ.mass = self.config.ball_density * circ.radius * circ.radius;
+        }
+        // sort before taking the snapshot so that previous_positions lines up with circles
+        self.circles
+            .sort_by(|a, b| a.radius.partial_cmp(&b.radius).unwrap());
+        self.previous_positions.clear();
+        self.previous_positions
+            .extend(self.circles.iter().map(|circ| circ.position));
+        let (width, height) = (self.width, self.height);
+        match self.config.gravity_tilt {
+            GravityTilt::Off => {}
+            GravityTilt::Keys => {
+                self.config.gravity_angle += input.tilt * GRAVITY_TILT_SPEED * delta_time;
+            }
+            GravityTilt::Mouse => {
+                let from_center = input.mouse_position - vec2(width / 2.0, height / 2.0);
+                if input.mouse_forces_enabled && from_center != Vec2::ZERO {
+                    self.config.gravity_angle = from_center.y.atan2(from_center.x).to_degrees();
+                }
+            }
+        }
+        self.config.gravity_angle = self.config.gravity_angle.rem_euclid(360.0);
+        // keep the attraction matrix the right size for however many species there are
+        let num_species = self.config.particle_life_species.clamp(1, MAX_SPECIES) as usize;
+        self.config.particle_life_species = num_species as u32;
+        self.config
+            .particle_life_matrix
+            .resize_with(num_species, Vec::new);
+        for row in &mut self.config.particle_life_matrix {
+            row.resize(num_species, 0.0);
+        }
+        self.species.clear();
+        if self.config.particle_life {
+            self.species.extend(
+                self.circles
+                    .iter()
+                    .map(|circ| species_of(circ, num_species)),
+            );
+        }
+        let config = &self.config;
+        let gravity = Vec2::from_angle(config.gravity_angle.to_radians()) * config.gravity_strength;
+        let Vec2 {
+            x: mouse_x,
+            y: mouse_y,
+        } = input.mouse_position;
+
+        // mouse control for starfield
+        if config.starfield {
+            // when the user drags the mouse, move the starfield in the opposite direction,
+            // by moving the starfield origin point
+            if input.left_mouse_down {
+                self.starfield_origin -= input.mouse_delta * delta_time * 100.0;
+            }
+        }
+        let starfield_origin = self.starfield_origin;
+        // pick up any change to which index is in use
+        if !self.spatial_index.is_configured_as(config) {
+            self.spatial_index = AnySpatialIndex::new(bounding_box(width, height), config);
+        }
+        // the spatial index holds indices into circles, so every ball sees its neighbours as
+        // they were at the start of the step, regardless of the order they're updated in
+        self.spatial_index.rebuild(
+            bounding_box(width, height),
+            self.circles.iter().map(|circ| circ.position).enumerate(),
+        );
+        if config.nbody {
+            if self.nbody_tree.settings() != quadtree_settings(config) {
+                self.nbody_tree =
+                    Quadtree::with_settings(bounding_box(width, height), quadtree_settings(config));
+            }
+            self.nbody_tree.rebuild(
+                bounding_box(width, height),
+                self.circles.iter().map(|circ| circ.position).enumerate(),
+            );
+            let circles = &self.circles;
+            self.nbody_tree
+                .compute_mass_distribution(|handle| circles[handle].mass);
+        }
+        // neighbours are found, and pushed and pulled, across any edges that wrap round
+        let wrap = self.wraparound();
+        // every ball's density has to be known before any of the pressure forces can be
+        self.densities.clear();
+        if config.fluid {
+            let mut neighbours = Vec::new();
+            for circ in &self.circles {
+                self.spatial_index.query_radius_wrapped(
+                    circ.position,
+                    config.fluid_smoothing_radius,
+                    &wrap,
+                    &mut neighbours,
+                );
+                let density = neighbours
+                    .iter()
+                    .map(|neighbour| {
+                        density_kernel(neighbour.distance, config.fluid_smoothing_radius)
+                    })
+                    .sum();
+                self.densities.push(density);
+            }
+        }
+        self.spring_accelerations.clear();
+        self.spring_accelerations
+            .resize(self.circles.len(), Vec2::ZERO);
+        if !self.springs.is_empty() {
+            let indices = self.circle_indices();
+            let circles = &self.circles;
+            // each spring pulls towards the nearest copy of the ball at its far end
+            let far_end = |a: usize, b: usize| Circle {
+                position: wrap.nearest_image(circles[a].position, circles[b].position),
+                ..circles[b]
+            };
+            self.springs.retain(
+                |spring| match (indices.get(&spring.a), indices.get(&spring.b)) {
+                    (Some(&a), Some(&b)) => !spring.is_broken(&circles[a], &far_end(a, b)),
+                    _ => false,
+                },
+            );
+            for spring in &self.springs {
+                let (a, b) = (indices[&spring.a], indices[&spring.b]);
+                let force = spring.force(&circles[a], &far_end(a, b));
+                self.spring_accelerations[a] += force / self.circles[a].mass;
+                self.spring_accelerations[b] -= force / self.circles[b].mass;
+            }
+        }
+        let joined: HashSet<(CircleId, CircleId)> = self
+            .springs
+            .iter()
+            .map(|spring| ordered_pair(spring.a, spring.b))
+            .collect();
+        let spring_accelerations = &self.spring_accelerations;
+        let circles = &self.circles;
+        let spatial_index = &self.spatial_index;
+        let nbody_tree = &self.nbody_tree;
+        let species = &self.species;
+        let densities = &self.densities;
+        let obstacles = &self.obstacles;
+        let portals = &self.portals;
+        let rng = &mut self.rng;
+        // two balls can only touch if they're closer than this ball's radius plus the biggest
+        // radius of any ball
+        let max_radius = circles.iter().map(|circ| circ.radius).fold(0.0, f32::max);
+        let mut neighbours: Vec<Neighbour> = Vec::new();
+        // balls that have just run into each other, found from where they were at the start of
+        // the step, before anything pushes them apart; each pair is only recorded by the ball
+        // with the lower id, and only if they've just come into contact, rather than being
+        // pressed together and jostling, so it's shallower than they've closed in the last step
+        let mut impacts = Vec::new();
+        if (config.merging || config.fragmenting) && !config.starfield && !config.fluid {
+            for circ in circles {
+                spatial_index.query_radius_wrapped(
+                    circ.position,
+                    circ.radius + max_radius,
+                    &wrap,
+                    &mut neighbours,
+                );
+                for neighbour in &neighbours {
+                    let other = &circles[neighbour.handle];
+                    if circ.id >= other.id || joined.contains(&ordered_pair(circ.id, other.id)) {
+                        continue;
+                    }
+                    let overlap = circ.radius + other.radius - neighbour.distance;
+                    let towards = (wrap.nearest_image(circ.position, other.position)
+                        - circ.position)
+                        .normalize_or_zero();
+                    let speed = (circ.velocity - other.velocity).dot(towards);
+                    let hit = overlap > 0.0
+                        && overlap <= speed * delta_time
+                        && (config.merging && speed >= config.merge_speed
+                            || config.fragmenting && speed >= config.fragment_speed);
+                    if hit {
+                        impacts.push(Impact {
+                            a: circ.id,
+                            b: other.id,
+                            speed,
+                        });
+                    }
+                }
+            }
+        }
+        let mut sticky_contacts = Vec::new();
+        // balls that have gone out through an edge, and which kind of edge it was
+        let mut leaving = Vec::new();
+        let x_edges = BoundaryMode::axis(config.boundary_left, config.boundary_right);
+        let y_edges = BoundaryMode::axis(config.boundary_top, config.boundary_bottom);
+        // balls that have wrapped round an edge or gone through a portal, which shouldn't be
+        // drawn sweeping across the window between where they were and where they are
+        let mut teleported = Vec::new();
+        // sticky balls look a little further than touching distance for balls to catch
+        let contact_reach = if config.stickiness > 0.0 {
+            STICKY_REACH
+        } else {
+            1.0
+        };
+        // pressure waves and stiff springs both grow without bound under explicit Euler, so the
+        // fluid and the balls on the ends of springs always update velocities before positions
+        let sprung: HashSet<CircleId> = self
+            .springs
+            .iter()
+            .flat_map(|spring| [spring.a, spring.b])
+            .collect();
+
+        let hot_region = config
+            .hot_wall
+            .region(config.thermal_wall_depth, width, height);
+        let cold_region = config
+            .cold_wall
+            .region(config.thermal_wall_depth, width, height);
+
+        let new_circles = circles
+            .iter()
+            .enumerate()
+            .map(|(index, circ)| {
+                let Vec2 { mut x, mut y } = circ.position;
+                let circle_size = circ.radius;
+                let velocity = circ.velocity;
+                let integrator = if (config.fluid || sprung.contains(&circ.id))
+                    && config.integrator == Integrator::ExplicitEuler
+                {
+                    Integrator::SemiImplicitEuler
+                } else {
+                    config.integrator
+                };
+                // the elastic model does its own, mass-weighted, separation of overlapping balls
+                if !config.allow_ball_intersection
+                    && !config.fluid
+                    && config.collision_model == CollisionModel::SoftRepel
+                {
+                    spatial_index.query_radius_wrapped(
+                        circ.position,
+                        circle_size + max_radius,
+                        &wrap,
+                        &mut neighbours,
+                    );
+                    for neighbour in &neighbours {
+                        let other = &circles[neighbour.handle];
+                        // balls joined by a spring are kept apart by the spring instead
+                        if other.id == circ.id || joined.contains(&ordered_pair(circ.id, other.id))
+                        {
+                            continue;
+                        }
+                        // sqrt (pow(abs(other_x - x), 2) + pow(abs(other_y - y), 2))
+                        let Vec2 {
+                            x: other_x,
+                            y: other_y,
+                        } = wrap.nearest_image(circ.position, other.position);
+                        let other_size = other.radius;
+                        let dist = vec2(other_x, other_y).distance(vec2(x, y));
+                        if dist < (circle_size + other_size) {
+                            let x_dist = other_x - x;
+                            let y_dist = other_y - y;
+                            x -= x_dist / 2.0;
+                            y -= y_dist / 2.0;
+                        }
+                    }
+                }
+
+                // hotter balls jiggle harder, with kicks growing as the square root of the
+                // temperature in both models, so that temperature goes with kinetic energy
+                let heat = if config.thermal {
+                    circ.temperature.max(0.0)
+                } else {
+                    1.0
+                };
+                let (jiggle_x, jiggle_y) = match config.jiggle_model {
+                    JiggleModel::Uniform => {
+                        let jiggle = config.jiggle * heat.sqrt();
+                        (
+                            rng.gen_range(-jiggle, jiggle),
+                            rng.gen_range(-jiggle, jiggle),
+                        )
+                    }
+                    // with damping γ and diffusion coefficient D, kicks with a standard deviation
+                    // of γ√(2D dt) make the mean squared displacement grow as 4Dt in 2D
+                    JiggleModel::Brownian => {
+                        let kick = config.brownian_damping
+                            * (2.0 * config.diffusion * heat * delta_time).sqrt();
+                        (rng.gen_normal() * kick, rng.gen_normal() * kick)
+                    }
+                };
+                let mut new_x = x;
+                let mut new_y = y;
+                let mut new_velocity = velocity;
+
+                if config.gravity_enabled {
+                    new_velocity += gravity * delta_time;
+                }
+                new_velocity += spring_accelerations[index] * delta_time;
+
+                // every ball pulls on every other in proportion to its mass
+                if config.nbody {
+                    new_velocity += nbody_tree.gravity_at(
+                        circ.position,
+                        config.nbody_theta,
+                        config.nbody_softening,
+                    ) * config.nbody_strength
+                        * delta_time;
+                }
+
+                let mut new_pos = vec2(new_x, new_y);
+                if integrator == Integrator::ExplicitEuler {
+                    new_pos += velocity * delta_time;
+                }
+                // the fluid has its own viscosity, and Brownian balls have their own damping
+                if config.jiggle_model == JiggleModel::Brownian {
+                    new_velocity -= velocity * config.brownian_damping * delta_time;
+                } else if !config.fluid
+                    && (!config.starfield && velocity.x != 0.0 || velocity.y != 0.0)
+                {
+                    new_velocity -= velocity.normalize() * delta_time * config.medium_viscosity;
+                }
+
+                // pressure pushes balls from where the fluid is denser to where it's thinner,
+                // and viscosity evens out their velocities
+                if config.fluid {
+                    spatial_index.query_radius_wrapped(
+                        circ.position,
+                        config.fluid_smoothing_radius,
+                        &wrap,
+                        &mut neighbours,
+                    );
+                    let smoothing_radius = config.fluid_smoothing_radius;
+                    let density = densities[index];
+                    let pressure = config.fluid_stiffness * (density - config.fluid_rest_density);
+                    let mut acceleration = Vec2::ZERO;
+                    for neighbour in &neighbours {
+                        let other = &circles[neighbour.handle];
+                        // balls sitting exactly on top of each other can't tell which way to go
+                        if other.id == circ.id || neighbour.distance == 0.0 {
+                            continue;
+                        }
+                        let other_density = densities[neighbour.handle];
+                        let other_pressure =
+                            config.fluid_stiffness * (other_density - config.fluid_rest_density);
+                        let away = (circ.position
+                            - wrap.nearest_image(circ.position, other.position))
+                            / neighbour.distance;
+                        acceleration += away
+                            * (pressure + other_pressure)
+                            * pressure_kernel_slope(neighbour.distance, smoothing_radius)
+                            / (2.0 * other_density);
+                        acceleration += (other.velocity - velocity)
+                            * config.fluid_viscosity
+                            * viscosity_kernel_laplacian(neighbour.distance, smoothing_radius)
+                            / other_density;
+                    }
+                    new_velocity += acceleration / density * delta_time;
+                }
+
+                if new_velocity.x < 0.01 && new_velocity.x > -0.01 {
+                    new_velocity.x = 0.0;
+                }
+                if new_velocity.y < 0.01 && new_velocity.y > -0.01 {
+                    new_velocity.y = 0.0;
+                }
+
+                // if starfield is enabled, move away from the center of the screen
+                if config.starfield {
+                    let from_center = vec2(x, y) - starfield_origin;
+                    let dist = from_center.length();
+                    if dist < circle_size {
+                        // pick a random direction to move in if you're really close to the center
+                        let mut rand_dir = vec2(0.0, 0.0);
+                        while rand_dir.x == 0.0 && rand_dir.y == 0.0 {
+                            rand_dir = vec2(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0));
+                        }
+                        new_velocity =
+                            rand_dir * delta_time * 10_f32.powf(config.warp_factor / 2.0);
+                    } else {
+                        // move away from the center
+                        new_velocity =
+                            from_center * delta_time * 10_f32.powf(config.warp_factor / 2.0);
+                    }
+                }
+
+                if config.boids {
+                    spatial_index.query_radius_wrapped(
+                        vec2(x, y),
+                        config.boids_perception_radius,
+                        &wrap,
+                        &mut neighbours,
+                    );
+                    let num_results = neighbours.len();
+                    let mut separation = Vec2::default();
+                    let mut alignment = Vec2::default();
+                    let mut cohesion = Vec2::default();
+
+                    for neighbour in &neighbours {
+                        let other = &circles[neighbour.handle];
+                        if other.id == circ.id {
+                            continue; // Skip self
+                        }
+
+                        let other_position = wrap.nearest_image(vec2(x, y), other.position);
+                        let to_other = other_position - vec2(x, y);
+
+                        // Separation: Move away from close neighbors
+                        if to_other.length() < config.separation_distance {
+                            separation -= to_other.normalize();
+                        }
+
+                        // Alignment: Align with the average velocity of neighbors
+                        alignment += other.velocity;
+
+                        // Cohesion: Move towards the average position of neighbors
+                        cohesion += other_position;
+                    }
+
+                    if num_results > 0 {
+                        if num_results > 1 {
+                            // turn alignment and cohesion into mean averages of position/velocity
+                            alignment /= (num_results - 1) as f32;
+                            cohesion /= (num_results - 1) as f32;
+                            separation /= (num_results - 1) as f32;
+                        }
+                        let mut avoid_walls = vec2(0.0, 0.0);
+                        if x < config.avoid_walls_distance
+                            || x > width - config.avoid_walls_distance
+                            || y < config.avoid_walls_distance
+                            || y > height - config.avoid_walls_distance
+                        {
+                            avoid_walls = vec2(width / 2.0, height / 2.0) - vec2(x, y);
+                            avoid_walls *= config.avoid_walls_weight;
+                        }
+                        separation *= config.separation_weight;
+                        alignment *= config.alignment_weight;
+                        cohesion = (cohesion - vec2(x, y)).normalize() * config.cohesion_weight;
+
+                        new_velocity += (separation + alignment + cohesion + avoid_walls)
+                            * delta_time
+                            * config.boid_amount;
+                    }
+                }
+
+                // if the magnitude of the velocity > max_velocity, clamp it back to that
+                if !config.starfield && new_velocity.length() > config.max_velocity {
+                    new_velocity = new_velocity.normalize() * config.max_velocity;
+                }
+
+                new_x = new_pos.x;
+                new_y = new_pos.y;
+
+                // collision detection; in fluid mode the pressure keeps balls apart instead
+                if !config.starfield && !config.fluid {
+                    spatial_index.query_radius_wrapped(
+                        vec2(x, y),
+                        (circle_size + max_radius) * contact_reach,
+                        &wrap,
+                        &mut neighbours,
+                    );
+                    // every elastic impulse is worked out from the velocities at the start of
+                    // the step, so a ball hit by several at once takes their average rather
+                    // than the sum, which would keep growing in a tightly packed pile
+                    let mut impulses = Vec2::ZERO;
+                    let mut hits = 0;
+                    for neighbour in &neighbours {
+                        let other = &circles[neighbour.handle];
+                        // balls joined by a spring are kept apart by the spring instead
+                        if other.id == circ.id || joined.contains(&ordered_pair(circ.id, other.id))
+                        {
+                            continue;
+                        }
+                        // sqrt (pow(abs(other_x - x), 2) + pow(abs(other_y - y), 2))
+                        let Vec2 {
+                            x: other_x,
+                            y: other_y,
+                        } = wrap.nearest_image(vec2(x, y), other.position);
+                        let other_size = other.radius;
+                        let dist = vec2(other_x, other_y).distance(vec2(x, y));
+                        // sticky balls catch others that come within reach, without them having
+                        // to actually overlap
+                        if config.stickiness > 0.0
+                            && circ.id < other.id
+                            && dist < (circle_size + other_size) * STICKY_REACH
+                            && (config.all_sticky || circ.sticky || other.sticky)
+                        {
+                            sticky_contacts.push((circ.id, other.id));
+                        }
+                        if dist >= (circle_size + other_size) {
+                            continue;
+                        }
+                        let x_dist = other_x - x;
+                        let y_dist = other_y - y;
+                        match config.collision_model {
+                            CollisionModel::SoftRepel => {
+                                new_velocity -= vec2(x_dist, y_dist).normalize()
+                                    * dist
+                                    * delta_time
+                                    * config.particle_repel_force;
+                            }
+                            CollisionModel::Elastic => {
+                                // both balls in a pair see the same impulse from opposite sides,
+                                // so momentum is conserved when only two balls meet
+                                let normal = vec2(x_dist, y_dist).normalize_or_zero();
+                                let inverse_mass = 1.0 / circ.mass;
+                                let other_inverse_mass = 1.0 / other.mass;
+                                let total_inverse_mass = inverse_mass + other_inverse_mass;
+                                let closing_speed = (other.velocity - velocity).dot(normal);
+                                if closing_speed < 0.0 {
+                                    let impulse = -(1.0 + config.restitution) * closing_speed
+                                        / total_inverse_mass;
+                                    impulses -= normal * impulse * inverse_mass;
+                                    hits += 1;
+                                }
+                                if !config.allow_ball_intersection {
+                                    // move apart by this ball's share of the overlap, ignoring
+                                    // a little of it so resting balls don't jitter
+                                    let overlap = circle_size + other_size - dist;
+                                    let correction = (overlap - COLLISION_SLOP).max(0.0)
+                                        * config.position_correction
+                                        * inverse_mass
+                                        / total_inverse_mass;
+                                    new_x -= normal.x * correction;
+                                    new_y -= normal.y * correction;
+                                }
+                            }
+                        }
+                    }
+                    if hits > 0 {
+                        new_velocity += impulses / hits as f32;
+                    }
+                }
+
+                // like charges repel and opposite charges attract
+                if config.charges && circ.charge != 0.0 {
+                    spatial_index.query_radius_wrapped(
+                        circ.position,
+                        config.charge_range,
+                        &wrap,
+                        &mut neighbours,
+                    );
+                    for neighbour in &neighbours {
+                        let other = &circles[neighbour.handle];
+                        if other.id == circ.id || other.charge == 0.0 {
+                            continue;
+                        }
+                        // balls can't get any closer than touching, which also stops the force
+                        // blowing up when two centres nearly coincide
+                        let dist = neighbour.distance.max(circle_size + other.radius);
+                        let direction = (wrap.nearest_image(circ.position, other.position)
+                            - circ.position)
+                            .normalize_or_zero();
+                        new_velocity -=
+                            direction * config.charge_strength * circ.charge * other.charge
+                                / (dist * dist * circ.mass)
+                                * delta_time;
+                    }
+                }
+
+                // each species is pulled towards or pushed away from each other species by
+                // however much the attraction matrix says
+                if config.particle_life {
+                    let attractions = &config.particle_life_matrix[species_of(circ, num_species)];
+                    spatial_index.query_radius_wrapped(
+                        circ.position,
+                        config.particle_life_radius,
+                        &wrap,
+                        &mut neighbours,
+                    );
+                    for neighbour in &neighbours {
+                        let other = &circles[neighbour.handle];
+                        if other.id == circ.id {
+                            continue;
+                        }
+                        let force = particle_life_force(
+                            neighbour.distance / config.particle_life_radius,
+                            attractions[species[neighbour.handle]],
+                        );
+                        let direction = (wrap.nearest_image(circ.position, other.position)
+                            - circ.position)
+                            .normalize_or_zero();
+                        new_velocity += direction
+                            * force
+                            * config.particle_life_radius
+                            * config.particle_life_strength
+                            * delta_time;
+                    }
+                }
+
+                // heat flows from hotter balls to cooler ones they're touching, and the hot and
+                // cold walls warm up or cool down the balls near them
+                let mut temperature = circ.temperature;
+                if config.thermal {
+                    spatial_index.query_radius_wrapped(
+                        circ.position,
+                        circle_size + max_radius,
+                        &wrap,
+                        &mut neighbours,
+                    );
+                    for neighbour in &neighbours {
+                        let other = &circles[neighbour.handle];
+                        if other.id == circ.id || neighbour.distance >= circle_size + other.radius {
+                            continue;
+                        }
+                        // bigger balls hold more heat, so their temperature changes more slowly
+                        temperature += (other.temperature - circ.temperature) * other.mass
+                            / (circ.mass + other.mass)
+                            * config.heat_conductivity
+                            * delta_time;
+                    }
+                    if hot_region.is_some_and(|region| region.contains(circ.position)) {
+                        temperature += (config.hot_temperature - circ.temperature)
+                            * config.wall_heat_rate
+                            * delta_time;
+                    }
+                    if cold_region.is_some_and(|region| region.contains(circ.position)) {
+                        temperature += (config.cold_temperature - circ.temperature)
+                            * config.wall_heat_rate
+                            * delta_time;
+                    }
+                    temperature = temperature.max(0.0);
+                }
+
+                if input.mouse_forces_enabled && !config.starfield {
+                    let mut mouse_gravity = 0.0;
+                    let mut mouse_distance = config.mouse_attract_distance;
+                    if input.left_mouse_down {
+                        mouse_gravity = -config.mouse_attract_force;
+                        mouse_distance *= 3.0;
+                    } else if input.right_mouse_down {
+                        mouse_gravity = config.mouse_repel_force;
+                    }
+                    let mouse_x_dist = x - mouse_x;
+                    let mouse_y_dist = y - mouse_y;
+                    let mouse_dist = (mouse_x_dist.powi(2) + mouse_y_dist.powi(2)).sqrt();
+                    if mouse_dist < mouse_distance {
+                        new_velocity += Vec2::new(mouse_x_dist, mouse_y_dist) * mouse_gravity;
+                    }
+                }
+
+                new_velocity += vec2(jiggle_x, jiggle_y);
+                if integrator != Integrator::ExplicitEuler {
+                    new_x += new_velocity.x * delta_time;
+                    new_y += new_velocity.y * delta_time;
+                }
+                if integrator == Integrator::PositionVerlet {
+                    new_velocity = (vec2(new_x, new_y) - circ.position) / delta_time;
+                    if !config.starfield && new_velocity.length() > config.max_velocity {
+                        new_velocity = new_velocity.normalize() * config.max_velocity;
+                    }
+                }
+
+                for obstacle in obstacles {
+                    if let Some((normal, depth)) = obstacle.contact(vec2(new_x, new_y), circle_size)
+                    {
+                        new_x += normal.x * depth;
+                        new_y += normal.y * depth;
+                        let speed_into = new_velocity.dot(normal);
+                        if speed_into < 0.0 {
+                            events.push(SimulationEvent::WallHit { circle_size });
+                            new_velocity -=
+                                normal * speed_into * (1.0 + config.obstacle_restitution);
+                        }
+                    }
+                }
+
+                // what happens at each edge depends on its boundary mode, except that stars
+                // always fly off the screen
+                let mut left_screen = false;
+                if config.starfield {
+                    if new_x >= width - circle_size
+                        || new_x <= circle_size
+                        || new_y >= height - circle_size
+                        || new_y <= circle_size
+                    {
+                        events.push(SimulationEvent::WallHit { circle_size });
+                        left_screen = true;
+                    }
+                } else {
+                    let (bounced_x, wrapped_x, leaving_x) = cross_edges(
+                        &mut new_x,
+                        &mut new_velocity.x,
+                        circle_size,
+                        width,
+                        x_edges,
+                        config.wall_restitution,
+                    );
+                    let (bounced_y, wrapped_y, leaving_y) = cross_edges(
+                        &mut new_y,
+                        &mut new_velocity.y,
+                        circle_size,
+                        height,
+                        y_edges,
+                        config.wall_restitution,
+                    );
+                    for _ in 0..bounced_x as usize + bounced_y as usize {
+                        events.push(SimulationEvent::WallHit { circle_size });
+                    }
+                    if wrapped_x || wrapped_y {
+                        teleported.push(index);
+                    }
+                    if let Some(mode) = leaving_x.or(leaving_y) {
+                        leaving.push((circ.id, mode));
+                    }
+                }
+
+                // a star that reaches the edge expires, to be replaced by a new one at the
+                // starfield origin, or without lifetimes just jumps straight back there
+                let mut age = circ.age;
+                if left_screen {
+                    if config.lifetimes {
+                        age = f32::INFINITY;
+                    } else {
+                        Vec2 { x: new_x, y: new_y } = starfield_origin;
+                        new_x += jiggle_x;
+                        new_y += jiggle_y;
+                        teleported.push(index);
+                    }
+                }
+
+                for portal in portals {
+                    if let Some(exit) =
+                        portal.teleport(vec2(new_x, new_y), new_velocity, circle_size)
+                    {
+                        Vec2 { x: new_x, y: new_y } = exit;
+                        teleported.push(index);
+                        break;
+                    }
+                }
+
+                // keep balls inside the edges that bounce them back
+                let reflects = |mode| config.starfield || mode == BoundaryMode::Reflect;
+                if reflects(x_edges.0) {
+                    new_x = new_x.max(circle_size);
+                }
+                if reflects(x_edges.1) {
+                    new_x = new_x.min(width - circle_size);
+                }
+                if reflects(y_edges.0) {
+                    new_y = new_y.max(circle_size);
+                }
+                if reflects(y_edges.1) {
+                    new_y = new_y.min(height - circle_size);
+                }
+                Circle {
+                    position: vec2(new_x, new_y),
+                    velocity: new_velocity,
+                    age,
+                    temperature,
+                    ..*circ
+                }
+            })
+            .collect();
+        self.circles = new_circles;
+        for index in teleported {
+            self.previous_positions[index] = self.circles[index].position;
+        }
+        self.resolve_impacts(&impacts);
+        self.form_bonds(&sticky_contacts, delta_time);
+        self.remove_leaving_balls(&leaving);
+
+        events
+    }
+}
@@ -0,0 +1,406 @@
+use crate::config::JiggleBallsConfig;
+use crate::grid::{GridQuery, SpatialHashGrid};
+use crate::quadtree::{Handle, Quadtree, QuadtreeQuery, QuadtreeSettings};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// An item found by a neighbour query, along with how far it is from the query point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbour {
+    pub handle: Handle,
+    pub distance: f32,
+}
+
+/// A way of finding the balls near a point, so the simulation doesn't have to check every pair.
+/// Items are handles (indices into the simulation's ball storage) stored at a position.
+pub trait SpatialIndex {
+    /// The iterator returned by [`SpatialIndex::query_rect`].
+    type Query<'a>: Iterator<Item = Handle>
+    where
+        Self: 'a;
+
+    /// Remove everything from the index and change the area it covers.
+    fn clear(&mut self, bounds: Rect);
+    /// The area the index covers.
+    fn bounds(&self) -> Rect;
+    /// How many items are in the index.
+    fn len(&self) -> usize;
+    fn insert(&mut self, handle: Handle, position: Vec2);
+    /// Move an item that's already in the index (or insert it if it isn't).
+    fn update(&mut self, handle: Handle, position: Vec2);
+    fn remove(&mut self, handle: Handle);
+    /// Where `handle` is stored, if it's in the index.
+    fn position(&self, handle: Handle) -> Option<Vec2>;
+    /// Every handle whose position lies inside `range`.
+    fn query_rect(&self, range: Rect) -> Self::Query<'_>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every item within `radius` of `center`, nearest first.  `results` is cleared before
+    /// being filled, so the same buffer can be reused for every query.
+    fn query_radius(&self, center: Vec2, radius: f32, results: &mut Vec<Neighbour>) {
+        results.clear();
+        for handle in self.query_rect(radius_range(center, radius)) {
+            if let Some(position) = self.position(handle) {
+                let distance = position.distance(center);
+                if distance <= radius {
+                    results.push(Neighbour { handle, distance });
+                }
+            }
+        }
+        results.sort_unstable_by(|a, b| {
+            a.distance
+                .total_cmp(&b.distance)
+                .then(a.handle.cmp(&b.handle))
+        });
+    }
+
+    /// Like [`SpatialIndex::query_radius`], but in an area that wraps round, so items just
+    /// over the opposite edge are found too, at the distance of whichever of their copies is
+    /// nearest.  Every item should be inside `wrap.bounds`.
+    fn query_radius_wrapped(
+        &self,
+        center: Vec2,
+        radius: f32,
+        wrap: &Wraparound,
+        results: &mut Vec<Neighbour>,
+    ) {
+        if !wrap.x && !wrap.y {
+            self.query_radius(center, radius, results);
+            return;
+        }
+        results.clear();
+        let period = wrap.period();
+        for shift_x in copies(center.x, radius, wrap.bounds.x, period.x) {
+            for shift_y in copies(center.y, radius, wrap.bounds.y, period.y) {
+                let copy = center + vec2(shift_x, shift_y);
+                for handle in self.query_rect(radius_range(copy, radius)) {
+                    if let Some(position) = self.position(handle) {
+                        let distance = position.distance(copy);
+                        if distance <= radius {
+                            results.push(Neighbour { handle, distance });
+                        }
+                    }
+                }
+            }
+        }
+        // a big enough radius can reach the same item through more than one copy
+        results.sort_unstable_by(|a, b| {
+            a.handle
+                .cmp(&b.handle)
+                .then(a.distance.total_cmp(&b.distance))
+        });
+        results.dedup_by_key(|neighbour| neighbour.handle);
+        results.sort_unstable_by(|a, b| {
+            a.distance
+                .total_cmp(&b.distance)
+                .then(a.handle.cmp(&b.handle))
+        });
+    }
+
+    /// The `k` items nearest to `center` (or all of them, if there are fewer than `k`), nearest
+    /// first.  `results` is cleared before being filled.
+    fn nearest_k(&self, center: Vec2, k: usize, results: &mut Vec<Neighbour>) {
+        results.clear();
+        let k = k.min(self.len());
+        if k == 0 {
+            return;
+        }
+        // start with the radius that would hold k items if they were spread out evenly, then
+        // keep widening the search until it finds enough
+        let bounds = self.bounds();
+        let mut radius = (bounds.w * bounds.h * k as f32 / (PI * self.len() as f32))
+            .sqrt()
+            .max(1.0);
+        while radius.is_finite() {
+            self.query_radius(center, radius, results);
+            if results.len() >= k {
+                break;
+            }
+            radius *= 2.0;
+        }
+        results.truncate(k);
+    }
+
+    /// Clear the index and fill it with `items` in one go.
+    fn rebuild(&mut self, bounds: Rect, items: impl IntoIterator<Item = (Handle, Vec2)>) {
+        self.clear(bounds);
+        for (handle, position) in items {
+            self.insert(handle, position);
+        }
+    }
+}
+
+/// An area whose opposite edges are joined along one or both axes, so anything that goes out
+/// through one edge comes back in through the other, like the surface of a torus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wraparound {
+    pub bounds: Rect,
+    /// whether the left and right edges are joined
+    pub x: bool,
+    /// whether the top and bottom edges are joined
+    pub y: bool,
+}
+
+impl Wraparound {
+    /// How far apart the copies of the area are along each axis, or 0 along an axis that
+    /// doesn't wrap.
+    pub fn period(&self) -> Vec2 {
+        vec2(
+            if self.x { self.bounds.w } else { 0.0 },
+            if self.y { self.bounds.h } else { 0.0 },
+        )
+    }
+
+    /// Where `to` appears to be when seen from `from`: whichever of its copies is nearest.
+    pub fn nearest_image(&self, from: Vec2, to: Vec2) -> Vec2 {
+        let period = self.period();
+        let offset = to - from;
+        let mut image = to;
+        if period.x > 0.0 {
+            image.x -= period.x * (offset.x / period.x).round();
+        }
+        if period.y > 0.0 {
+            image.y -= period.y * (offset.y / period.y).round();
+        }
+        image
+    }
+}
+
+/// The square to look for items within `radius` of `center` in.  Rects leave out their right
+/// and bottom edges, so it's widened a touch to take in the items exactly `radius` away.
+fn radius_range(center: Vec2, radius: f32) -> Rect {
+    let margin = (center.abs().max_element() + radius).max(1.0) * f32::EPSILON * 4.0;
+    let half = radius + margin;
+    Rect::new(center.x - half, center.y - half, half * 2.0, half * 2.0)
+}
+
+/// How far to shift a query at `coordinate` along one axis to look at each copy of the area,
+/// which starts at `start` and repeats every `period` (0 if it doesn't), leaving out copies that
+/// are too far away for `radius` to reach into the area.
+fn copies(coordinate: f32, radius: f32, start: f32, period: f32) -> impl Iterator<Item = f32> {
+    [0.0, period, -period]
+        .into_iter()
+        .enumerate()
+        .filter(move |&(i, shift)| {
+            i == 0
+                || period > 0.0
+                    && coordinate + shift - radius <= start + period
+                    && coordinate + shift + radius >= start
+        })
+        .map(|(_, shift)| shift)
+}
+
+/// Which [`SpatialIndex`] implementation the simulation should use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpatialIndexKind {
+    #[default]
+    Quadtree,
+    /// a uniform grid of `grid_cell_size` cells, hashed into buckets
+    Grid,
+}
+
+/// Any of the spatial indexes, picked at runtime by [`SpatialIndexKind`].
+#[derive(Debug, Clone)]
+pub enum AnySpatialIndex {
+    Quadtree(Quadtree),
+    Grid(SpatialHashGrid),
+}
+
+impl AnySpatialIndex {
+    /// The index picked by `config.spatial_index`, set up with the rest of `config`'s settings.
+    pub fn new(bounds: Rect, config: &JiggleBallsConfig) -> Self {
+        match config.spatial_index {
+            SpatialIndexKind::Quadtree => AnySpatialIndex::Quadtree(Quadtree::with_settings(
+                bounds,
+                quadtree_settings(config),
+            )),
+            SpatialIndexKind::Grid => {
+                AnySpatialIndex::Grid(SpatialHashGrid::new(bounds, config.grid_cell_size))
+            }
+        }
+    }
+
+    /// Whether this is the index that [`AnySpatialIndex::new`] would build for `config`.
+    pub fn is_configured_as(&self, config: &JiggleBallsConfig) -> bool {
+        match self {
+            AnySpatialIndex::Quadtree(quadtree) => {
+                config.spatial_index == SpatialIndexKind::Quadtree
+                    && quadtree.settings() == quadtree_settings(config)
+            }
+            AnySpatialIndex::Grid(grid) => {
+                config.spatial_index == SpatialIndexKind::Grid
+                    && grid.cell_size() == config.grid_cell_size.max(1.0)
+            }
+        }
+    }
+
+    pub fn kind(&self) -> SpatialIndexKind {
+        match self {
+            AnySpatialIndex::Quadtree(_) => SpatialIndexKind::Quadtree,
+            AnySpatialIndex::Grid(_) => SpatialIndexKind::Grid,
+        }
+    }
+}
+
+/// The quadtree settings `config` asks for.
+pub(crate) fn quadtree_settings(config: &JiggleBallsConfig) -> QuadtreeSettings {
+    QuadtreeSettings {
+        node_capacity: config.quadtree_node_capacity,
+        max_depth: config.quadtree_max_depth,
+        out_of_bounds: config.quadtree_out_of_bounds,
+    }
+}
+
+/// The iterator returned by [`AnySpatialIndex::query_rect`].
+pub enum AnyQuery<'a> {
+    Quadtree(QuadtreeQuery<'a>),
+    Grid(GridQuery<'a>),
+}
+
+impl Iterator for AnyQuery<'_> {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Handle> {
+        match self {
+            AnyQuery::Quadtree(query) => query.next(),
+            AnyQuery::Grid(query) => query.next(),
+        }
+    }
+}
+
+impl SpatialIndex for AnySpatialIndex {
+    type Query<'a> = AnyQuery<'a>;
+
+    fn clear(&mut self, bounds: Rect) {
+        match self {
+            AnySpatialIndex::Quadtree(index) => index.clear(bounds),
+            AnySpatialIndex::Grid(index) => index.clear(bounds),
+        }
+    }
+
+    fn bounds(&self) -> Rect {
+        match self {
+            AnySpatialIndex::Quadtree(index) => index.get_bounds(),
+            AnySpatialIndex::Grid(index) => index.bounds(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            AnySpatialIndex::Quadtree(index) => index.len(),
+            AnySpatialIndex::Grid(index) => index.len(),
+        }
+    }
+
+    fn insert(&mut self, handle: Handle, position: Vec2) {
+        match self {
+            AnySpatialIndex::Quadtree(index) => index.insert(handle, position),
+            AnySpatialIndex::Grid(index) => index.insert(handle, position),
+        }
+    }
+
+    fn update(&mut self, handle: Handle, position: Vec2) {
+        match self {
+            AnySpatialIndex::Quadtree(index) => index.update(handle, position),
+            AnySpatialIndex::Grid(index) => index.update(handle, position),
+        }
+    }
+
+    fn remove(&mut self, handle: Handle) {
+        match self {
+            AnySpatialIndex::Quadtree(index) => index.remove(handle),
+            AnySpatialIndex::Grid(index) => index.remove(handle),
+        }
+    }
+
+    fn position(&self, handle: Handle) -> Option<Vec2> {
+        match self {
+            AnySpatialIndex::Quadtree(index) => index.position(handle),
+            AnySpatialIndex::Grid(index) => index.position(handle),
+        }
+    }
+
+    fn query_rect(&self, range: Rect) -> AnyQuery<'_> {
+        match self {
+            AnySpatialIndex::Quadtree(index) => AnyQuery::Quadtree(index.query(range)),
+            AnySpatialIndex::Grid(index) => AnyQuery::Grid(index.query(range)),
+        }
+    }
+}
+
+impl SpatialIndex for Quadtree {
+    type Query<'a> = QuadtreeQuery<'a>;
+
+    fn clear(&mut self, bounds: Rect) {
+        Quadtree::clear(self, bounds)
+    }
+
+    fn bounds(&self) -> Rect {
+        self.get_bounds()
+    }
+
+    fn len(&self) -> usize {
+        Quadtree::len(self)
+    }
+
+    fn insert(&mut self, handle: Handle, position: Vec2) {
+        Quadtree::insert(self, handle, position)
+    }
+
+    fn update(&mut self, handle: Handle, position: Vec2) {
+        Quadtree::update(self, handle, position)
+    }
+
+    fn remove(&mut self, handle: Handle) {
+        Quadtree::remove(self, handle)
+    }
+
+    fn position(&self, handle: Handle) -> Option<Vec2> {
+        Quadtree::position(self, handle)
+    }
+
+    fn query_rect(&self, range: Rect) -> QuadtreeQuery<'_> {
+        self.query(range)
+    }
+}
+
+impl SpatialIndex for SpatialHashGrid {
+    type Query<'a> = GridQuery<'a>;
+
+    fn clear(&mut self, bounds: Rect) {
+        SpatialHashGrid::clear(self, bounds)
+    }
+
+    fn bounds(&self) -> Rect {
+        SpatialHashGrid::bounds(self)
+    }
+
+    fn len(&self) -> usize {
+        SpatialHashGrid::len(self)
+    }
+
+    fn insert(&mut self, handle: Handle, position: Vec2) {
+        SpatialHashGrid::insert(self, handle, position)
+    }
+
+    fn update(&mut self, handle: Handle, position: Vec2) {
+        SpatialHashGrid::update(self, handle, position)
+    }
+
+    fn remove(&mut self, handle: Handle) {
+        SpatialHashGrid::remove(self, handle)
+    }
+
+    fn position(&self, handle: Handle) -> Option<Vec2> {
+        SpatialHashGrid::position(self, handle)
+    }
+
+    fn query_rect(&self, range: Rect) -> GridQuery<'_> {
+        self.query(range)
+    }
+}
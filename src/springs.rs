@@ -0,0 +1,38 @@
+use crate::{Circle, CircleId};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A damped spring joining two balls, pulling them together when they're further apart than
+/// `rest_length` and pushing them apart when they're closer.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Spring {
+    pub a: CircleId,
+    pub b: CircleId,
+    pub rest_length: f32,
+    /// force per pixel of stretch
+    pub stiffness: f32,
+    /// force per pixel per second that the ends move towards or away from each other
+    pub damping: f32,
+    /// how far the spring can be stretched beyond `rest_length`, as a fraction of it, before it
+    /// snaps; springs without one never do
+    #[serde(default)]
+    pub break_stretch: Option<f32>,
+}
+
+impl Spring {
+    /// The force the spring puts on `a`, which is attached to its first end; `b` gets the same
+    /// force in the opposite direction.
+    pub fn force(&self, a: &Circle, b: &Circle) -> Vec2 {
+        let direction = (b.position - a.position).normalize_or_zero();
+        let stretch = a.position.distance(b.position) - self.rest_length;
+        let separating_speed = (b.velocity - a.velocity).dot(direction);
+        direction * (self.stiffness * stretch + self.damping * separating_speed)
+    }
+
+    /// Whether the balls at either end have been pulled far enough apart to snap the spring.
+    pub fn is_broken(&self, a: &Circle, b: &Circle) -> bool {
+        self.break_stretch.is_some_and(|break_stretch| {
+            a.position.distance(b.position) > self.rest_length * (1.0 + break_stretch)
+        })
+    }
+}
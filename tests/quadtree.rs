@@ -0,0 +1,429 @@
+//! Property tests checking the spatial indexes against a brute force search over every item.
+
+use jiggleballs::grid::SpatialHashGrid;
+use jiggleballs::quadtree::{Handle, OutOfBounds, Quadtree, QuadtreeSettings};
+use jiggleballs::spatial::{Neighbour, SpatialIndex, Wraparound};
+use macroquad::math::{vec2, Rect, Vec2};
+use proptest::prelude::*;
+use std::collections::HashMap;
+
+const BOUNDS: Rect = Rect {
+    x: -50.0,
+    y: 20.0,
+    w: 800.0,
+    h: 600.0,
+};
+const MAX_HANDLE: Handle = 64;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(Handle, Vec2),
+    Update(Handle, Vec2),
+    Remove(Handle),
+}
+
+/// A coordinate that's either anywhere in (and a bit beyond) the bounds, or exactly on one of
+/// the lines the quadtree splits along, which is where off-by-one mistakes hide.
+fn coordinate(start: f32, size: f32) -> impl Strategy<Value = f32> {
+    prop_oneof![
+        (start - size..start + size * 2.0),
+        (0..=16u32).prop_map(move |i| start + size * i as f32 / 16.0),
+    ]
+}
+
+fn position() -> impl Strategy<Value = Vec2> {
+    (
+        coordinate(BOUNDS.x, BOUNDS.w),
+        coordinate(BOUNDS.y, BOUNDS.h),
+    )
+        .prop_map(|(x, y)| vec2(x, y))
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => (0..MAX_HANDLE, position()).prop_map(|(handle, position)| Op::Insert(handle, position)),
+        2 => (0..MAX_HANDLE, position()).prop_map(|(handle, position)| Op::Update(handle, position)),
+        1 => (0..MAX_HANDLE).prop_map(Op::Remove),
+    ]
+}
+
+fn settings() -> impl Strategy<Value = QuadtreeSettings> {
+    (
+        0..8u32,
+        0..16u32,
+        prop_oneof![Just(OutOfBounds::Grow), Just(OutOfBounds::Clamp)],
+    )
+        .prop_map(
+            |(node_capacity, max_depth, out_of_bounds)| QuadtreeSettings {
+                node_capacity,
+                max_depth,
+                out_of_bounds,
+            },
+        )
+}
+
+fn range() -> impl Strategy<Value = Rect> {
+    (position(), 0.0..400.0f32, 0.0..400.0f32)
+        .prop_map(|(corner, w, h)| Rect::new(corner.x, corner.y, w, h))
+}
+
+/// Where the quadtree should end up storing an item inserted at `position`.
+fn stored_position(settings: QuadtreeSettings) -> impl Fn(Vec2) -> Vec2 {
+    move |position| match settings.out_of_bounds {
+        OutOfBounds::Grow => position,
+        OutOfBounds::Clamp => position.clamp(BOUNDS.point(), BOUNDS.point() + BOUNDS.size()),
+    }
+}
+
+/// Apply `ops` to both the index and a plain map of where each item should end up.
+fn apply(
+    index: &mut impl SpatialIndex,
+    ops: &[Op],
+    stored_position: impl Fn(Vec2) -> Vec2,
+) -> HashMap<Handle, Vec2> {
+    let mut model = HashMap::new();
+    for op in ops {
+        match *op {
+            Op::Insert(handle, position) => {
+                index.insert(handle, position);
+                model.insert(handle, stored_position(position));
+            }
+            Op::Update(handle, position) => {
+                index.update(handle, position);
+                model.insert(handle, stored_position(position));
+            }
+            Op::Remove(handle) => {
+                index.remove(handle);
+                model.remove(&handle);
+            }
+        }
+    }
+    model
+}
+
+fn brute_force_radius(model: &HashMap<Handle, Vec2>, center: Vec2, radius: f32) -> Vec<Neighbour> {
+    let mut expected: Vec<Neighbour> = model
+        .iter()
+        .map(|(&handle, position)| Neighbour {
+            handle,
+            distance: position.distance(center),
+        })
+        .filter(|neighbour| neighbour.distance <= radius)
+        .collect();
+    expected.sort_by(|a, b| {
+        a.distance
+            .total_cmp(&b.distance)
+            .then(a.handle.cmp(&b.handle))
+    });
+    expected
+}
+
+fn check_queries(
+    index: &impl SpatialIndex,
+    model: &HashMap<Handle, Vec2>,
+    ranges: &[Rect],
+    centers: &[(Vec2, f32)],
+) -> Result<(), TestCaseError> {
+    prop_assert_eq!(index.len(), model.len());
+    for handle in 0..MAX_HANDLE {
+        prop_assert_eq!(index.position(handle), model.get(&handle).copied());
+    }
+
+    for range in ranges {
+        let mut found: Vec<Handle> = index.query_rect(*range).collect();
+        found.sort();
+        let mut expected: Vec<Handle> = model
+            .iter()
+            .filter(|(_, position)| range.contains(**position))
+            .map(|(&handle, _)| handle)
+            .collect();
+        expected.sort();
+        prop_assert_eq!(found, expected, "query_rect({:?})", range);
+    }
+
+    let mut results = Vec::new();
+    for &(center, radius) in centers {
+        index.query_radius(center, radius, &mut results);
+        prop_assert_eq!(&results, &brute_force_radius(model, center, radius));
+
+        let k = radius as usize % 10;
+        index.nearest_k(center, k, &mut results);
+        let mut expected = brute_force_radius(model, center, f32::INFINITY);
+        expected.truncate(k);
+        prop_assert_eq!(&results, &expected, "nearest_k({}, {})", center, k);
+    }
+    Ok(())
+}
+
+fn inside_position() -> impl Strategy<Value = Vec2> {
+    (BOUNDS.x..BOUNDS.x + BOUNDS.w, BOUNDS.y..BOUNDS.y + BOUNDS.h).prop_map(|(x, y)| vec2(x, y))
+}
+
+/// Like [`brute_force_radius`], but measuring to whichever copy of each item is nearest.
+fn brute_force_wrapped(
+    model: &HashMap<Handle, Vec2>,
+    center: Vec2,
+    radius: f32,
+    wrap: &Wraparound,
+) -> Vec<Neighbour> {
+    let period = wrap.period();
+    let shifts = [-1.0, 0.0, 1.0];
+    let mut expected: Vec<Neighbour> = model
+        .iter()
+        .map(|(&handle, position)| {
+            let distance = shifts
+                .iter()
+                .flat_map(|x| shifts.iter().map(move |y| vec2(*x, *y) * period))
+                .map(|shift| position.distance(center + shift))
+                .fold(f32::INFINITY, f32::min);
+            Neighbour { handle, distance }
+        })
+        .filter(|neighbour| neighbour.distance <= radius)
+        .collect();
+    expected.sort_by(|a, b| {
+        a.distance
+            .total_cmp(&b.distance)
+            .then(a.handle.cmp(&b.handle))
+    });
+    expected
+}
+
+proptest! {
+    #[test]
+    fn quadtree_matches_brute_force(
+        settings in settings(),
+        ops in prop::collection::vec(op(), 0..200),
+        ranges in prop::collection::vec(range(), 1..10),
+        centers in prop::collection::vec((position(), 0.0..300.0f32), 1..10),
+    ) {
+        let mut quadtree = Quadtree::with_settings(BOUNDS, settings);
+        let model = apply(&mut quadtree, &ops, stored_position(settings));
+        check_queries(&quadtree, &model, &ranges, &centers)?;
+
+        let bounds = quadtree.get_bounds();
+        for position in model.values() {
+            prop_assert!(position.cmpge(bounds.point()).all());
+            prop_assert!(position.cmple(bounds.point() + bounds.size()).all());
+        }
+    }
+
+    #[test]
+    fn quadtree_rebuild_matches_brute_force(
+        settings in settings(),
+        positions in prop::collection::vec(position(), 0..300),
+        ranges in prop::collection::vec(range(), 1..10),
+        centers in prop::collection::vec((position(), 0.0..300.0f32), 1..10),
+    ) {
+        let items: Vec<(Handle, Vec2)> = positions
+            .iter()
+            .enumerate()
+            .map(|(handle, &position)| (handle % MAX_HANDLE, position))
+            .collect();
+        let mut quadtree = Quadtree::with_settings(BOUNDS, settings);
+        quadtree.rebuild(BOUNDS, items.iter().copied());
+        let stored_position = stored_position(settings);
+        let model = items
+            .iter()
+            .map(|&(handle, position)| (handle, stored_position(position)))
+            .collect();
+        check_queries(&quadtree, &model, &ranges, &centers)?;
+    }
+
+    #[test]
+    fn barnes_hut_matches_brute_force(
+        settings in settings(),
+        items in prop::collection::vec((position(), 0.1..100.0f32), 0..200),
+        center in position(),
+        softening in 0.0..20.0f32,
+    ) {
+        let mut quadtree = Quadtree::with_settings(BOUNDS, settings);
+        quadtree.rebuild(BOUNDS, items.iter().map(|(position, _)| *position).enumerate());
+        quadtree.compute_mass_distribution(|handle| items[handle].1);
+        let stored_position = stored_position(settings);
+        let pull = |theta| quadtree.gravity_at(center, theta, softening);
+
+        let mut expected = Vec2::ZERO;
+        let mut scale = 0.0;
+        for &(position, mass) in &items {
+            let offset = stored_position(position) - center;
+            let distance_squared = offset.length_squared() + softening * softening;
+            if distance_squared > 0.0 {
+                let contribution = offset * mass / (distance_squared * distance_squared.sqrt());
+                expected += contribution;
+                scale += contribution.length();
+            }
+        }
+        // opening every node gives the exact sum, give or take rounding
+        prop_assert!(pull(0.0).distance(expected) <= scale * 1e-4 + 1e-6);
+        // and the approximation shouldn't be wildly off
+        prop_assert!(pull(0.5).distance(expected) <= scale * 0.1 + 1e-6);
+    }
+
+    #[test]
+    fn wrapped_queries_match_brute_force(
+        settings in settings(),
+        cell_size in 1.0..200.0f32,
+        positions in prop::collection::vec(inside_position(), 0..200),
+        centers in prop::collection::vec((inside_position(), 0.0..500.0f32), 1..10),
+        wrap_x in any::<bool>(),
+        wrap_y in any::<bool>(),
+    ) {
+        let wrap = Wraparound { bounds: BOUNDS, x: wrap_x, y: wrap_y };
+        let items = positions.iter().copied().enumerate();
+        let mut quadtree = Quadtree::with_settings(BOUNDS, settings);
+        quadtree.rebuild(BOUNDS, items.clone());
+        let mut grid = SpatialHashGrid::new(BOUNDS, cell_size);
+        grid.rebuild(BOUNDS, items.clone());
+        let model: HashMap<Handle, Vec2> = items.collect();
+
+        let mut results = Vec::new();
+        for &(center, radius) in &centers {
+            let expected = brute_force_wrapped(&model, center, radius, &wrap);
+            quadtree.query_radius_wrapped(center, radius, &wrap, &mut results);
+            prop_assert_eq!(&results, &expected);
+            grid.query_radius_wrapped(center, radius, &wrap, &mut results);
+            prop_assert_eq!(&results, &expected);
+            for neighbour in &expected {
+                let image = wrap.nearest_image(center, model[&neighbour.handle]);
+                prop_assert!((image.distance(center) - neighbour.distance).abs() < 1e-2);
+            }
+        }
+    }
+
+    #[test]
+    fn radius_queries_find_items_exactly_on_the_edge(
+        settings in settings(),
+        cell_size in 1.0..200.0f32,
+        center in inside_position(),
+        radius in prop_oneof![Just(0.0f32), 0.0..300.0f32],
+        others in prop::collection::vec(inside_position(), 0..50),
+        wrap_x in any::<bool>(),
+        wrap_y in any::<bool>(),
+    ) {
+        // the centre itself, and the four points exactly `radius` away along the axes
+        let on_edge = [
+            vec2(0.0, 0.0),
+            vec2(radius, 0.0),
+            vec2(-radius, 0.0),
+            vec2(0.0, radius),
+            vec2(0.0, -radius),
+        ]
+        .map(|offset| center + offset);
+        let positions: Vec<Vec2> = on_edge
+            .into_iter()
+            .filter(|position| BOUNDS.contains(*position))
+            .chain(others)
+            .collect();
+        let items = positions.iter().copied().enumerate();
+        let mut quadtree = Quadtree::with_settings(BOUNDS, settings);
+        quadtree.rebuild(BOUNDS, items.clone());
+        let mut grid = SpatialHashGrid::new(BOUNDS, cell_size);
+        grid.rebuild(BOUNDS, items.clone());
+        let model: HashMap<Handle, Vec2> = items.collect();
+
+        let mut results = Vec::new();
+        let expected = brute_force_radius(&model, center, radius);
+        prop_assert!(!expected.is_empty());
+        quadtree.query_radius(center, radius, &mut results);
+        prop_assert_eq!(&results, &expected);
+        grid.query_radius(center, radius, &mut results);
+        prop_assert_eq!(&results, &expected);
+
+        let wrap = Wraparound { bounds: BOUNDS, x: wrap_x, y: wrap_y };
+        let expected = brute_force_wrapped(&model, center, radius, &wrap);
+        quadtree.query_radius_wrapped(center, radius, &wrap, &mut results);
+        prop_assert_eq!(&results, &expected);
+        grid.query_radius_wrapped(center, radius, &wrap, &mut results);
+        prop_assert_eq!(&results, &expected);
+    }
+
+    #[test]
+    fn grid_matches_brute_force(
+        cell_size in 1.0..200.0f32,
+        ops in prop::collection::vec(op(), 0..200),
+        ranges in prop::collection::vec(range(), 1..10),
+        centers in prop::collection::vec((position(), 0.0..300.0f32), 1..10),
+    ) {
+        let mut grid = SpatialHashGrid::new(BOUNDS, cell_size);
+        let model = apply(&mut grid, &ops, |position| position);
+        check_queries(&grid, &model, &ranges, &centers)?;
+    }
+}
+
+#[test]
+fn items_on_the_far_edges_are_found() {
+    let mut quadtree = Quadtree::with_settings(
+        BOUNDS,
+        QuadtreeSettings {
+            node_capacity: 1,
+            ..Default::default()
+        },
+    );
+    let far_corner = BOUNDS.point() + BOUNDS.size();
+    let positions = [
+        BOUNDS.point(),
+        BOUNDS.center(),
+        far_corner,
+        vec2(far_corner.x, BOUNDS.y),
+        vec2(BOUNDS.x, far_corner.y),
+    ];
+    for (handle, position) in positions.into_iter().enumerate() {
+        quadtree.insert(handle, position);
+    }
+    assert_eq!(quadtree.len(), positions.len());
+    assert_eq!(quadtree.get_bounds(), BOUNDS);
+    let mut found: Vec<Handle> = quadtree
+        .query(Rect::new(
+            BOUNDS.x,
+            BOUNDS.y,
+            BOUNDS.w + 1.0,
+            BOUNDS.h + 1.0,
+        ))
+        .collect();
+    found.sort();
+    assert_eq!(found, [0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn piled_up_items_stop_at_max_depth() {
+    let mut quadtree = Quadtree::with_settings(
+        BOUNDS,
+        QuadtreeSettings {
+            node_capacity: 0,
+            max_depth: 3,
+            ..Default::default()
+        },
+    );
+    for handle in 0..1000 {
+        quadtree.insert(handle, BOUNDS.center());
+    }
+    assert_eq!(quadtree.len(), 1000);
+    assert_eq!(quadtree.query(BOUNDS).count(), 1000);
+}
+
+#[test]
+fn out_of_bounds_items_grow_or_clamp_the_root() {
+    let outside = vec2(BOUNDS.x - 1000.0, BOUNDS.y + BOUNDS.h * 3.0);
+
+    let mut grow = Quadtree::new(BOUNDS);
+    grow.insert(0, outside);
+    assert_eq!(grow.position(0), Some(outside));
+    assert!(grow.get_bounds().contains(outside));
+    assert_eq!(grow.query(grow.get_bounds()).collect::<Vec<_>>(), [0]);
+
+    let mut clamp = Quadtree::with_settings(
+        BOUNDS,
+        QuadtreeSettings {
+            out_of_bounds: OutOfBounds::Clamp,
+            ..Default::default()
+        },
+    );
+    clamp.insert(0, outside);
+    assert_eq!(clamp.position(0), Some(vec2(BOUNDS.x, BOUNDS.y + BOUNDS.h)));
+    assert_eq!(clamp.get_bounds(), BOUNDS);
+
+    // positions that aren't finite can't go anywhere sensible, so they're left out
+    grow.insert(1, vec2(f32::NAN, 0.0));
+    grow.insert(2, vec2(f32::INFINITY, 0.0));
+    assert_eq!(grow.len(), 1);
+}
@@ -0,0 +1,644 @@
+//! Tests driving a whole `Simulation` without a window.
+
+use jiggleballs::config::JiggleBallsConfig;
+use jiggleballs::fluid::density_kernel;
+use jiggleballs::scene::{Emitter, Portal, Sink};
+use jiggleballs::simulation::{
+    BoundaryMode, CollisionModel, InputState, Integrator, JiggleModel, Simulation, Wall,
+};
+use jiggleballs::{Circle, CircleId};
+use macroquad::color::WHITE;
+use macroquad::math::{vec2, Vec2};
+
+const WIDTH: f32 = 800.0;
+const HEIGHT: f32 = 600.0;
+
+fn seeded_config(seed: u64) -> JiggleBallsConfig {
+    JiggleBallsConfig {
+        seed: Some(seed),
+        num_circles: 300,
+        ..Default::default()
+    }
+}
+
+/// The mouse wandering around with the buttons going up and down, so replays have some input to
+/// follow.
+fn input_at(step: usize) -> InputState {
+    let t = step as f32 / 60.0;
+    InputState {
+        mouse_position: vec2(400.0 + 200.0 * t.cos(), 300.0 + 150.0 * t.sin()),
+        mouse_delta: vec2(-200.0 * t.sin(), 150.0 * t.cos()) / 60.0,
+        left_mouse_down: step % 90 < 30,
+        right_mouse_down: step % 90 >= 60,
+        mouse_forces_enabled: true,
+        tilt: 0.0,
+    }
+}
+
+/// Every ball's position and velocity, as raw bits so that nothing is compared loosely.
+fn state_bits(sim: &Simulation) -> Vec<[u32; 4]> {
+    sim.circles
+        .iter()
+        .map(|circ| {
+            [
+                circ.position.x.to_bits(),
+                circ.position.y.to_bits(),
+                circ.velocity.x.to_bits(),
+                circ.velocity.y.to_bits(),
+            ]
+        })
+        .collect()
+}
+
+fn run(config: JiggleBallsConfig, steps: usize) -> Simulation {
+    let mut sim = Simulation::new(config, WIDTH, HEIGHT);
+    for step in 0..steps {
+        sim.step(sim.fixed_delta_time(), &input_at(step));
+    }
+    sim
+}
+
+#[test]
+fn the_same_seed_replays_bit_for_bit() {
+    let first = run(seeded_config(42), 300);
+    let second = run(seeded_config(42), 300);
+    assert_eq!(first.seed(), second.seed());
+    assert_eq!(state_bits(&first), state_bits(&second));
+}
+
+#[test]
+fn different_seeds_give_different_runs() {
+    let first = run(seeded_config(42), 300);
+    let second = run(seeded_config(43), 300);
+    assert_ne!(state_bits(&first), state_bits(&second));
+}
+
+/// Nothing but the balls themselves: no jiggle, drag, speed limit or mouse.
+fn still_config() -> JiggleBallsConfig {
+    JiggleBallsConfig {
+        seed: Some(1),
+        num_circles: 0,
+        jiggle: 0.0,
+        medium_viscosity: 0.0,
+        max_velocity: 10_000.0,
+        ..Default::default()
+    }
+}
+
+/// Add the given balls, each a (position, radius, velocity), with fresh ids from the
+/// simulation and the masses it would give them.
+fn add_balls(sim: &mut Simulation, balls: &[(Vec2, f32, Vec2)]) {
+    for &(position, radius, velocity) in balls {
+        let id = sim.next_circle_id();
+        let mut circ = Circle::new(id, position, radius, WHITE);
+        circ.velocity = velocity;
+        circ.mass = sim.config.ball_density * radius * radius;
+        sim.circles.push(circ);
+    }
+}
+
+/// A simulation holding just the given balls.
+fn with_balls(config: JiggleBallsConfig, balls: &[(Vec2, f32, Vec2)]) -> Simulation {
+    let mut sim = Simulation::new(config, WIDTH, HEIGHT);
+    add_balls(&mut sim, balls);
+    sim
+}
+
+fn run_for(sim: &mut Simulation, steps: usize) {
+    for _ in 0..steps {
+        sim.step(sim.fixed_delta_time(), &InputState::default());
+    }
+}
+
+fn momentum(sim: &Simulation) -> Vec2 {
+    sim.circles
+        .iter()
+        .fold(Vec2::ZERO, |total, circ| total + circ.velocity * circ.mass)
+}
+
+fn kinetic_energy(sim: &Simulation) -> f32 {
+    sim.circles
+        .iter()
+        .map(|circ| 0.5 * circ.mass * circ.velocity.length_squared())
+        .sum()
+}
+
+/// Run a perfectly elastic collision between two balls heading straight at each other, and
+/// check it conserves momentum and kinetic energy.
+fn check_elastic_collision(radius_a: f32, radius_b: f32) -> Simulation {
+    let config = JiggleBallsConfig {
+        collision_model: CollisionModel::Elastic,
+        restitution: 1.0,
+        ..still_config()
+    };
+    let mut sim = with_balls(
+        config,
+        &[
+            (vec2(300.0, 300.0), radius_a, vec2(100.0, 0.0)),
+            (vec2(500.0, 300.0), radius_b, vec2(-100.0, 0.0)),
+        ],
+    );
+    let (momentum_before, energy_before) = (momentum(&sim), kinetic_energy(&sim));
+    for _ in 0..60 {
+        sim.step(sim.fixed_delta_time(), &InputState::default());
+    }
+    sim.circles.sort_by_key(|circ| circ.id);
+    // they've bounced off each other and are heading apart
+    assert!(sim.circles[1].velocity.x > sim.circles[0].velocity.x);
+    let scale = energy_before.sqrt();
+    assert!(momentum(&sim).distance(momentum_before) < scale * 1e-3);
+    assert!((kinetic_energy(&sim) - energy_before).abs() < energy_before * 1e-3);
+    sim
+}
+
+#[test]
+fn elastic_head_on_collision_conserves_momentum_and_energy() {
+    let sim = check_elastic_collision(20.0, 20.0);
+    // equal balls just swap velocities
+    assert!((sim.circles[0].velocity.x + 100.0).abs() < 0.1);
+    assert!((sim.circles[1].velocity.x - 100.0).abs() < 0.1);
+}
+
+#[test]
+fn elastic_collision_between_unequal_masses_conserves_momentum_and_energy() {
+    let sim = check_elastic_collision(10.0, 30.0);
+    // with masses of 100 and 900, the light ball bounces back at 260 and the heavy one carries
+    // on at 60
+    assert!((sim.circles[0].velocity.x + 260.0).abs() < 0.5);
+    assert!((sim.circles[1].velocity.x + 60.0).abs() < 0.5);
+}
+
+/// Drop a ball from rest for half a second with `integrator`, returning how far it fell and how
+/// fast it's going, along with how far and fast it should be by the exact solution.
+fn free_fall(integrator: Integrator) -> ((f32, f32), (f32, f32)) {
+    let config = JiggleBallsConfig {
+        integrator,
+        gravity_enabled: true,
+        ..still_config()
+    };
+    let gravity = config.gravity_strength;
+    let mut sim = with_balls(config, &[(vec2(400.0, 100.0), 10.0, Vec2::ZERO)]);
+    let steps = 30;
+    for _ in 0..steps {
+        sim.step(sim.fixed_delta_time(), &InputState::default());
+    }
+    let t = steps as f32 * sim.fixed_delta_time();
+    let ball = sim.circles[0];
+    (
+        (ball.position.y - 100.0, ball.velocity.y),
+        (0.5 * gravity * t * t, gravity * t),
+    )
+}
+
+/// All three integrators are first order, so after `t` seconds the distance fallen is off by
+/// at most g·dt·t/2, one way or the other.
+fn check_free_fall(integrator: Integrator) -> f32 {
+    let ((fallen, speed), (exact_fallen, exact_speed)) = free_fall(integrator);
+    let error_bound = exact_speed / 60.0 / 2.0;
+    assert!(
+        (fallen - exact_fallen).abs() <= error_bound * 1.01,
+        "{integrator:?} fell {fallen} rather than {exact_fallen}"
+    );
+    assert!((speed - exact_speed).abs() < exact_speed * 1e-3);
+    fallen - exact_fallen
+}
+
+#[test]
+fn explicit_euler_free_fall_lags_behind_the_exact_solution() {
+    assert!(check_free_fall(Integrator::ExplicitEuler) < 0.0);
+}
+
+#[test]
+fn semi_implicit_euler_free_fall_runs_ahead_of_the_exact_solution() {
+    assert!(check_free_fall(Integrator::SemiImplicitEuler) > 0.0);
+}
+
+#[test]
+fn position_verlet_free_fall_runs_ahead_of_the_exact_solution() {
+    assert!(check_free_fall(Integrator::PositionVerlet) > 0.0);
+}
+
+#[test]
+fn balls_coming_out_of_a_portal_are_drawn_where_they_come_out() {
+    let mut sim = with_balls(
+        still_config(),
+        &[(vec2(200.0, 300.0), 5.0, vec2(300.0, 0.0))],
+    );
+    sim.portals.push(Portal {
+        a: vec2(300.0, 300.0),
+        b: vec2(600.0, 150.0),
+        radius: 20.0,
+    });
+    for _ in 0..30 {
+        sim.step(sim.fixed_delta_time(), &InputState::default());
+        if sim.circles[0].position.y < 200.0 {
+            break;
+        }
+    }
+    assert!(sim.circles[0].position.distance(vec2(625.0, 150.0)) < 10.0);
+    assert_eq!(sim.interpolated_position(0), sim.circles[0].position);
+}
+
+#[test]
+fn balls_wrapping_round_an_edge_are_drawn_where_they_come_in() {
+    let config = JiggleBallsConfig {
+        boundary_left: BoundaryMode::Wrap,
+        boundary_right: BoundaryMode::Wrap,
+        ..still_config()
+    };
+    let mut sim = with_balls(config, &[(vec2(795.0, 300.0), 10.0, vec2(600.0, 0.0))]);
+    sim.step(sim.fixed_delta_time(), &InputState::default());
+    assert!(sim.circles[0].position.x < 100.0);
+    assert_eq!(sim.interpolated_position(0), sim.circles[0].position);
+}
+
+#[test]
+fn sinks_delete_the_balls_that_fall_into_them() {
+    let mut sim = with_balls(
+        still_config(),
+        &[
+            (vec2(100.0, 100.0), 10.0, Vec2::ZERO),
+            (vec2(400.0, 300.0), 10.0, Vec2::ZERO),
+            (vec2(410.0, 290.0), 5.0, Vec2::ZERO),
+        ],
+    );
+    sim.sinks.push(Sink {
+        center: vec2(400.0, 300.0),
+        radius: 30.0,
+    });
+    sim.step(sim.fixed_delta_time(), &InputState::default());
+    assert_eq!(sim.circles.len(), 1);
+    assert_eq!(sim.circles[0].id, CircleId(0));
+}
+
+#[test]
+fn emitters_fire_balls_at_their_rate() {
+    let mut sim = Simulation::new(still_config(), WIDTH, HEIGHT);
+    sim.emitters.push(Emitter {
+        position: vec2(100.0, 300.0),
+        rate: 15.0,
+        ..Default::default()
+    });
+    // two seconds' worth
+    for _ in 0..120 {
+        sim.step(sim.fixed_delta_time(), &InputState::default());
+    }
+    assert!((29..=30).contains(&sim.circles.len()));
+}
+
+/// The fluid's density around each ball, worked out from scratch, on a window that wraps round
+/// both ways.
+fn fluid_densities(sim: &Simulation, width: f32, height: f32) -> Vec<f32> {
+    let smoothing_radius = sim.config.fluid_smoothing_radius;
+    sim.circles
+        .iter()
+        .map(|circ| {
+            sim.circles
+                .iter()
+                .map(|other| {
+                    let offset = (other.position - circ.position).abs();
+                    let offset = offset.min(vec2(width, height) - offset);
+                    density_kernel(offset.length(), smoothing_radius)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+#[test]
+fn fluid_settles_at_its_rest_density() {
+    // a torus, so there are no walls for the fluid to thin out against, holding exactly enough
+    // balls to fill it at the rest density
+    let (width, height) = (200.0, 200.0);
+    let config = JiggleBallsConfig {
+        fluid: true,
+        boundary_left: BoundaryMode::Wrap,
+        boundary_right: BoundaryMode::Wrap,
+        boundary_top: BoundaryMode::Wrap,
+        boundary_bottom: BoundaryMode::Wrap,
+        ..still_config()
+    };
+    let rest_density = config.fluid_rest_density;
+    let mut sim = Simulation::new(config, width, height);
+    // bunched up in a jumbled grid over the left half
+    let (columns, rows) = (10, 20);
+    let balls: Vec<(Vec2, f32, Vec2)> = (0..columns * rows)
+        .map(|i| {
+            let (column, row) = ((i % columns) as f32, (i / columns) as f32);
+            let jumble = vec2((i * 7 % 5) as f32, (i * 3 % 5) as f32);
+            let position = vec2(column * 10.0 + 5.0, row * 10.0 + 5.0) + jumble;
+            (position, 3.0, Vec2::ZERO)
+        })
+        .collect();
+    add_balls(&mut sim, &balls);
+    assert_eq!(sim.circles.len() as f32, rest_density * width * height);
+    for _ in 0..300 {
+        sim.step(sim.fixed_delta_time(), &InputState::default());
+    }
+    // it's spread out evenly, come to rest, and is everywhere close to the rest density
+    let fastest = sim
+        .circles
+        .iter()
+        .map(|circ| circ.velocity.length())
+        .fold(0.0, f32::max);
+    assert!(fastest < 1.0, "still moving at {fastest}");
+    for density in fluid_densities(&sim, width, height) {
+        assert!((density - rest_density).abs() < rest_density * 0.05);
+    }
+}
+
+/// Two balls of the given radii heading straight at each other, closing at 200 pixels per
+/// second, and bound to meet within a second.
+fn head_on(config: JiggleBallsConfig, radius_a: f32, radius_b: f32) -> Simulation {
+    with_balls(
+        config,
+        &[
+            (vec2(300.0, 300.0), radius_a, vec2(150.0, 0.0)),
+            // not a whole number of steps apart, so they meet partway through one
+            (vec2(461.0, 300.0), radius_b, vec2(-50.0, 0.0)),
+        ],
+    )
+}
+
+fn total_mass(sim: &Simulation) -> f32 {
+    sim.circles.iter().map(|circ| circ.mass).sum()
+}
+
+#[test]
+fn merged_balls_keep_their_area_and_momentum() {
+    let config = JiggleBallsConfig {
+        merging: true,
+        ..still_config()
+    };
+    let mut sim = head_on(config, 10.0, 20.0);
+    let momentum_before = momentum(&sim);
+    for _ in 0..60 {
+        if sim.circles.len() != 2 {
+            break;
+        }
+        sim.step(sim.fixed_delta_time(), &InputState::default());
+    }
+    let merged = sim.circles[0];
+    assert!((merged.radius * merged.radius - 500.0).abs() < 1e-2);
+    assert!(momentum(&sim).distance(momentum_before) < momentum_before.length() * 1e-4);
+}
+
+#[test]
+fn fragments_keep_the_mass_of_the_ball_they_came_from() {
+    let config = JiggleBallsConfig {
+        fragmenting: true,
+        fragment_count: 4,
+        ..still_config()
+    };
+    // the small ball is already as small as balls get, so only the big one breaks up
+    let mut sim = head_on(config, 20.0, 5.0);
+    let mass_before = total_mass(&sim);
+    for _ in 0..60 {
+        if sim.circles.len() != 2 {
+            break;
+        }
+        sim.step(sim.fixed_delta_time(), &InputState::default());
+    }
+    assert_eq!(sim.circles.len(), 5);
+    assert!((total_mass(&sim) - mass_before).abs() < mass_before * 1e-4);
+    // the pieces start off spread round where the ball was, all inside it
+    let pieces: Vec<Circle> = sim
+        .circles
+        .iter()
+        .filter(|circ| circ.radius == 10.0)
+        .copied()
+        .collect();
+    assert_eq!(pieces.len(), 4);
+    let middle = pieces
+        .iter()
+        .fold(Vec2::ZERO, |sum, piece| sum + piece.position)
+        / 4.0;
+    for (i, piece) in pieces.iter().enumerate() {
+        assert!(piece.position.distance(middle) + piece.radius <= 20.0 + 1e-3);
+        for other in &pieces[i + 1..] {
+            assert!(piece.position.distance(other.position) > 1.0);
+        }
+    }
+}
+
+#[test]
+fn a_resting_bonded_pair_stays_bonded() {
+    let config = JiggleBallsConfig {
+        stickiness: 1.0,
+        all_sticky: true,
+        ..still_config()
+    };
+    // close enough to stick, but not quite touching, so the bond starts off a little stretched
+    let mut sim = with_balls(
+        config,
+        &[
+            (vec2(400.0, 300.0), 5.0, Vec2::ZERO),
+            (vec2(411.0, 300.0), 5.0, Vec2::ZERO),
+        ],
+    );
+    sim.step(sim.fixed_delta_time(), &InputState::default());
+    assert_eq!(sim.springs.len(), 1);
+    for _ in 0..600 {
+        sim.step(sim.fixed_delta_time(), &InputState::default());
+    }
+    assert_eq!(sim.springs.len(), 1);
+    let gap = sim.circles[0].position.distance(sim.circles[1].position);
+    assert!((gap - 10.0).abs() < 0.5, "the balls are {gap} apart");
+}
+
+fn total_heat(sim: &Simulation) -> f32 {
+    sim.circles
+        .iter()
+        .map(|circ| circ.mass * circ.temperature)
+        .sum()
+}
+
+#[test]
+fn heat_flowing_between_balls_is_conserved() {
+    let config = JiggleBallsConfig {
+        thermal: true,
+        hot_wall: Wall::None,
+        cold_wall: Wall::None,
+        allow_ball_intersection: true,
+        ..still_config()
+    };
+    // a block of big and small, hot and cold balls, all overlapping their neighbours
+    let balls: Vec<(Vec2, f32, Vec2)> = (0..40)
+        .map(|i| {
+            let position = vec2(300.0 + (i % 8) as f32 * 25.0, 200.0 + (i / 8) as f32 * 25.0);
+            let radius = if i % 3 == 0 { 20.0 } else { 14.0 };
+            (position, radius, Vec2::ZERO)
+        })
+        .collect();
+    let mut sim = with_balls(config, &balls);
+    for (i, circ) in sim.circles.iter_mut().enumerate() {
+        circ.temperature = (i % 5) as f32;
+    }
+    let heat_before = total_heat(&sim);
+    for _ in 0..120 {
+        sim.step(sim.fixed_delta_time(), &InputState::default());
+    }
+    assert!((total_heat(&sim) - heat_before).abs() < heat_before * 1e-4);
+    // and it has actually flowed, so none of the balls are as hot or cold as they started
+    let (coldest, hottest) = sim
+        .circles
+        .iter()
+        .fold((f32::MAX, 0.0f32), |(lo, hi), circ| {
+            (lo.min(circ.temperature), hi.max(circ.temperature))
+        });
+    assert!(
+        coldest > 0.0 && hottest < 4.0,
+        "from {coldest} to {hottest}"
+    );
+}
+
+/// The mean squared distance the balls have moved from `start`, which holds where each one
+/// started, by id.
+fn mean_squared_displacement(sim: &Simulation, start: &[Vec2]) -> f32 {
+    let total: f32 = sim
+        .circles
+        .iter()
+        .map(|circ| circ.position.distance_squared(start[circ.id.0 as usize]))
+        .sum();
+    total / sim.circles.len() as f32
+}
+
+#[test]
+fn brownian_balls_spread_out_at_four_times_the_diffusion_coefficient() {
+    let config = JiggleBallsConfig {
+        jiggle_model: JiggleModel::Brownian,
+        diffusion: 200.0,
+        brownian_damping: 2.0,
+        ..still_config()
+    };
+    let diffusion = config.diffusion;
+    // tiny balls, far from the walls and each other, so they hardly ever bump into anything
+    let balls: Vec<(Vec2, f32, Vec2)> = (0..300)
+        .map(|i| {
+            let position = vec2(
+                175.0 + (i % 20) as f32 * 25.0,
+                125.0 + (i / 20) as f32 * 25.0,
+            );
+            (position, 0.5, Vec2::ZERO)
+        })
+        .collect();
+    let start: Vec<Vec2> = balls.iter().map(|ball| ball.0).collect();
+    let mut sim = with_balls(config, &balls);
+    // give their velocities a couple of seconds to settle, after which the mean squared
+    // displacement grows by 4D every second
+    run_for(&mut sim, 120);
+    let early = mean_squared_displacement(&sim, &start);
+    run_for(&mut sim, 480);
+    let late = mean_squared_displacement(&sim, &start);
+    let expected = 4.0 * diffusion * 8.0;
+    assert!(
+        ((late - early) - expected).abs() < expected * 0.1,
+        "grew by {} rather than {expected}",
+        late - early
+    );
+}
+
+/// A single ball heading for the right-hand edge, which has the given boundary mode.
+fn heading_right(right: BoundaryMode, left: BoundaryMode) -> Simulation {
+    let config = JiggleBallsConfig {
+        boundary_left: left,
+        boundary_right: right,
+        ..still_config()
+    };
+    with_balls(config, &[(vec2(770.0, 300.0), 10.0, vec2(600.0, 0.0))])
+}
+
+#[test]
+fn an_edge_opposite_one_that_wraps_wraps_as_well() {
+    let mut sim = heading_right(BoundaryMode::Reflect, BoundaryMode::Wrap);
+    run_for(&mut sim, 5);
+    assert!(sim.circles[0].position.x < 100.0);
+    assert!(sim.circles[0].velocity.x > 0.0);
+}
+
+#[test]
+fn absorbing_edges_delete_the_balls_that_touch_them() {
+    let mut sim = heading_right(BoundaryMode::Absorb, BoundaryMode::Reflect);
+    run_for(&mut sim, 5);
+    assert!(sim.circles.is_empty());
+}
+
+#[test]
+fn respawning_edges_fire_a_new_ball_from_an_emitter() {
+    let mut sim = heading_right(BoundaryMode::Respawn, BoundaryMode::Reflect);
+    // an emitter that only fires balls to replace the ones that leave
+    sim.emitters.push(Emitter {
+        position: vec2(100.0, 300.0),
+        rate: 0.0,
+        ..Default::default()
+    });
+    let leaving = sim.circles[0].id;
+    run_for(&mut sim, 5);
+    assert_eq!(sim.circles.len(), 1);
+    assert_ne!(sim.circles[0].id, leaving);
+    assert!(sim.circles[0].position.distance(vec2(100.0, 300.0)) < 20.0);
+}
+
+#[test]
+fn expired_balls_respawn_from_an_emitter() {
+    let config = JiggleBallsConfig {
+        lifetimes: true,
+        min_lifetime: 1.0,
+        max_lifetime: 1.0,
+        respawn_expired: true,
+        ..still_config()
+    };
+    let mut sim = with_balls(config, &[(vec2(600.0, 300.0), 10.0, Vec2::ZERO)]);
+    // an emitter that only fires balls to replace the ones that expire
+    sim.emitters.push(Emitter {
+        position: vec2(100.0, 300.0),
+        rate: 0.0,
+        speed: 0.0,
+        ..Default::default()
+    });
+    let expiring = sim.circles[0].id;
+    run_for(&mut sim, 62);
+    assert_eq!(sim.circles.len(), 1);
+    assert_ne!(sim.circles[0].id, expiring);
+    assert!(sim.circles[0].position.distance(vec2(100.0, 300.0)) < 1.0);
+}
+
+#[test]
+fn open_edges_let_balls_out_until_they_get_too_far_away() {
+    let mut sim = heading_right(BoundaryMode::Open, BoundaryMode::Reflect);
+    // a second in, it's flown straight out of the window
+    run_for(&mut sim, 60);
+    assert_eq!(sim.circles.len(), 1);
+    assert!(sim.circles[0].position.x > WIDTH + 500.0);
+    // and two seconds after that it's over 1000 pixels away, so it's gone
+    run_for(&mut sim, 120);
+    assert!(sim.circles.is_empty());
+}
+
+/// The mean kinetic energy of a dense pile of elastic balls settling under gravity with
+/// `integrator`, over the last second of five.
+fn settled_pile_energy(integrator: Integrator) -> f32 {
+    let config = JiggleBallsConfig {
+        seed: Some(7),
+        num_circles: 150,
+        gravity_enabled: true,
+        collision_model: CollisionModel::Elastic,
+        integrator,
+        ..Default::default()
+    };
+    let mut sim = Simulation::new(config, WIDTH, HEIGHT);
+    run_for(&mut sim, 240);
+    let mut energy = 0.0;
+    for _ in 0..60 {
+        run_for(&mut sim, 1);
+        energy += kinetic_energy(&sim) / 60.0;
+    }
+    energy
+}
+
+#[test]
+fn position_verlet_keeps_dense_piles_calmer_than_explicit_euler() {
+    let euler = settled_pile_energy(Integrator::ExplicitEuler);
+    let verlet = settled_pile_energy(Integrator::PositionVerlet);
+    assert!(verlet < euler, "{verlet} against {euler}");
+}